@@ -1,293 +1,848 @@
-use crate::core::*;
+use reat::*;
+
+use reat::{
+    encoding::escape,
+    glob::Glob,
+    index::{ Index, Refresh },
+    journal::Journal,
+    json::Json,
+    matcher::Matcher,
+    mirror::{ self, Counterpart },
+    protect::Protections,
+    query::Query,
+    sort::{ self, SortOptions },
+    walk::Walker,
+};
 
 use std::{
-    path::Path,
+    path::{ Path, PathBuf },
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
     fmt::Display,
     collections::{ HashSet, HashMap },
 };
 
 use zen_colour::*;
 
-pub fn print_list<P: AsRef<Path> + Display>(path: P, print_filename: bool, verbose: bool) {
-    let Ok(xattrs) = xattr::list(&path)
+// Get `key` from the index if it holds `path`, otherwise from the file itself.
+// A missing attribute, or one that is not text, is `None`, and the file failing to read an error.
+fn lookup(ctx: &Context, index: Option<&Index>, path: &str, key: &str)
+ -> Result<Option<String>, ReatError>
+{
+    match index.filter(|_| Index::covers(key)).and_then(|index| index.entry(ctx, path)) {
+        Some(entry) => Ok(entry.get(key).and_then(|val| String::from_utf8(val.to_vec()).ok())),
+        None => match get_string(ctx, path, key) {
+            Ok((_, val)) => Ok(Some(val)),
+            Err(ReatError::NotFound | ReatError::NonUtf8) => Ok(None),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+// Complain that `path` could not be read while doing `verb`.
+fn read_failed(path: &str, verb: &str, err: &ReatError) -> Outcome {
+    errln!("{BOLD}{GREEN}{path}{RESET}{RED}{BOLD}: could not {YELLOW}{verb}{RED}: {err}.{RESET}");
+    Outcome::Failed
+}
+
+// Get `attr` as `get_osstr` does, but escape key names that are not UTF-8 instead of failing.
+fn read_attr<P: AsRef<Path>>(ctx: &Context, path: P, attr: &OsStr)
+ -> Result<((String, KeyType), Vec<u8>), ReatError>
+{
+    match get_osstr(ctx, &path, attr) {
+        Err(ReatError::NonUtf8) => {
+            let value = get_raw_osstr(ctx, &path, attr)?;
+            let key = escape(attr.as_bytes());
+            let (key, kt) = split_key(&key);
+            Ok(((key.to_string(), kt), value))
+        },
+        res => res,
+    }
+}
+
+/// Everything was done, or at least one file matched.
+pub const EXIT_SUCCESS: u8 = 0;
+/// Nothing matched or no attribute was found, like grep.
+pub const EXIT_NO_MATCH: u8 = 1;
+/// The arguments could not be used.
+pub const EXIT_USAGE: u8 = 2;
+/// Some operations failed.
+pub const EXIT_PARTIAL: u8 = 3;
+/// Every operation failed.
+pub const EXIT_FAILURE: u8 = 4;
+
+/// What a single operation came to.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Done,
+    Failed,
+    /// Nothing matched, or the attribute was not there to get.
+    Unmatched,
+}
+
+/// Counts outcomes to decide the exit code.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub struct Status {
+    pub done: usize,
+    pub failed: usize,
+    pub unmatched: usize,
+    pub usage: bool,
+}
+
+impl Status {
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Done => self.done += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Unmatched => self.unmatched += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: Status) {
+        self.done += other.done;
+        self.failed += other.failed;
+        self.unmatched += other.unmatched;
+        self.usage |= other.usage;
+    }
+
+    /// Report an argument error.
+    pub fn usage<D: Display>(&mut self, msg: D) {
+        errln!("{msg}");
+        self.usage = true;
+    }
+
+    pub fn code(&self) -> u8 {
+        match (self.done, self.failed, self.unmatched) {
+            _ if self.usage => EXIT_USAGE,
+            (0, 1.., 0) => EXIT_FAILURE,
+            (_, 1.., _) => EXIT_PARTIAL,
+            (0, 0, 1..) => EXIT_NO_MATCH,
+            _ => EXIT_SUCCESS,
+        }
+    }
+}
+
+/// How results are written.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Output {
+    /// Coloured text.
+    #[default]
+    Human,
+    /// One JSON array holding every record.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+}
+
+/// Writes records as they come, as a JSON array or as separate lines.
+#[derive(Debug, Default)]
+pub struct Emitter {
+    pub output: Output,
+    /// End paths with a NUL byte instead of a newline.
+    pub null: bool,
+    count: usize,
+}
+
+impl Emitter {
+    pub fn new(output: Output, null: bool) -> Self {
+        Self { output, null, count: 0 }
+    }
+
+    pub fn human(&self) -> bool {
+        self.output == Output::Human
+    }
+
+    pub fn emit(&mut self, record: Json) {
+        match self.output {
+            Output::Human => { },
+            Output::Json if self.count == 0 => print!("[\n{record}"),
+            Output::Json => print!(",\n{record}"),
+            Output::Ndjson => println!("{record}"),
+        }
+        self.count += 1;
+    }
+
+    /// Print a path that matched, as a record when writing JSON.
+    pub fn path(&mut self, path: &str) {
+        match self.output {
+            Output::Human if self.null => print!("{path}\0"),
+            Output::Human => println!("{path}"),
+            _ => self.emit(Json::object([("path", path.into())])),
+        }
+    }
+
+    /// Close the JSON array.
+    pub fn finish(&self) {
+        match self.output {
+            Output::Json if self.count == 0 => println!("[]"),
+            Output::Json => println!("\n]"),
+            _ => { },
+        }
+    }
+}
+
+fn attr_record(
+    path: &str, key: &str, ktype: KeyType, value: &[u8], encoding: Encoding, codec: ListCodec
+) -> Json {
+    let (shown, binary) = reat::encoding::display_value(value, encoding);
+    let items = if binary { Json::Null } else { codec.decode(&shown).into() };
+    Json::object([
+        ("path", path.into()),
+        ("namespace", ktype.name().into()),
+        ("key", key.into()),
+        ("value", shown.into()),
+        ("binary", binary.into()),
+        ("items", items),
+        ("error", Json::Null),
+    ])
+}
+
+fn error_record(path: &str, key: Option<&str>, err: &str) -> Json {
+    Json::object([("path", path.into()), ("key", key.into()), ("error", err.into())])
+}
+
+// The filename in front of a message, when there are several files.
+fn prefix<P: Display>(path: P, print_filename: bool) -> String {
+    if print_filename {
+        format!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ")
+    } else {
+        String::new()
+    }
+}
+
+// Whether the fully qualified `key` may change from `old` to `new`, complaining if a protection
+// rule forbids it.
+fn allowed(
+    protect: &Protections, pre: &str, verb: &str, key: &str, old: Option<&[u8]>,
+    new: Option<&[u8]>, edit: bool
+) -> bool {
+    let res = protect.check(key, old, new, edit);
+    let key = key.strip_prefix("user.").unwrap_or(key);
+    match res {
+        Ok(()) => true,
+        Err(rule) => {
+            errln!(
+"{pre}{BOLD}{RED}Could not {YELLOW}{verb}{RED} {DEFAULT}{key}{RED} without {YELLOW}force{RED}, it is {rule}!{RESET}"
+            );
+            false
+        },
+    }
+}
+
+// Whether the list in `key` may be edited, possibly dropping items, complaining if not.
+fn editable<P: AsRef<Path>>(
+    ctx: &Context, protect: &Protections, pre: &str, verb: &str, path: P, key: &str, drops: bool
+) -> bool {
+    match protect.check_edit(&qualify(key), drops && get(ctx, path, key).is_ok()) {
+        Ok(()) => true,
+        Err(rule) => {
+            errln!(
+"{pre}{BOLD}{RED}Could not {YELLOW}{verb}{RED} {DEFAULT}{key}{RED} without {YELLOW}force{RED}, it is {rule}!{RESET}"
+            );
+            false
+        },
+    }
+}
+
+// The namespace in front of a key, nothing for the user namespace.
+fn namespace(kt: &KeyType) -> String {
+    match kt {
+        KeyType::User => String::new(),
+        KeyType::Other(namespace) if namespace.is_empty() =>
+            format!("{MAGENTA}(no namespace) {RESET}"),
+        kt => format!("{MAGENTA}({}) {RESET}", kt.name()),
+    }
+}
+
+fn show(value: &[u8], encoding: Encoding) -> String {
+    match reat::encoding::display_value(value, encoding) {
+        (string, false) => string,
+        (string, true) => format!("{MAGENTA}(binary) {RESET}{string}"),
+    }
+}
+
+pub fn print_list<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, print_filename: bool, verbose: bool, encoding: Encoding,
+    out: &mut Emitter
+) -> Outcome {
+    if !out.human() {
+        let spath = path.to_string();
+        let Ok(xattrs) = list(ctx, &path) else {
+            out.emit(error_record(&spath, None, "could not list attributes"));
+            return Outcome::Failed;
+        };
+        let mut records = Vec::new();
+        for attr in xattrs {
+            match read_attr(ctx, &path, &attr) {
+                Ok(((key, kt), value)) => records.push(((kt, key), value)),
+                Err(err) => out.emit(error_record(&spath, attr.to_str(), &err.to_string())),
+            }
+        }
+        records.sort();
+        for ((kt, key), value) in records {
+            out.emit(attr_record(&spath, &key, kt, &value, encoding, ctx.codec(&key)));
+        }
+        return Outcome::Done;
+    }
+    let Ok(xattrs) = list(ctx, &path)
     else {
-        println!(
+        errln!(
             "{BOLD}{GREEN}{path}{RESET}{RED}{BOLD}: could not {YELLOW}list{RED} attributes.{RESET}"
         );
-        return;
+        return Outcome::Failed;
     };
-    let mut user = Vec::new();
-    let mut system = Vec::new();
-    let mut trusted = Vec::new();
-    let mut security = Vec::new();
+    let mut records = Vec::new();
     let mut empty = true;
     for attr in xattrs {
         empty = false;
-        match get_osstr(&path, &attr) {
-            Some(((key, KeyType::User), value)) => user.push((key, value)),
-            Some(((key, KeyType::System), value)) => system.push((key, value)),
-            Some(((key, KeyType::Trusted), value)) => trusted.push((key, value)),
-            Some(((key, KeyType::Security), value)) => security.push((key, value)),
-            None => { },
-        }
-    }
-    user.sort();
-    system.sort();
-    trusted.sort();
-    security.sort();
+        if let Ok(((key, kt), value)) = read_attr(ctx, &path, &attr) {
+            records.push(((kt, key), value));
+        }
+    }
+    records.sort();
     if (print_filename || verbose) && !empty {
-        println!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET}");
+        outln!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET}");
     } else if verbose && empty {
-        println!("{BOLD}{GREEN}{path}{RESET}{GREEN}: {RED}{BOLD}❌{RESET}");
+        outln!("{BOLD}{GREEN}{path}{RESET}{GREEN}: {RED}{BOLD}❌{RESET}");
     }
-    for (key, value) in user {
-        println!("  {BOLD}{key}{RESET}: {value}");
+    for ((kt, key), value) in records {
+        outln!("  {}{BOLD}{key}{RESET}: {}", namespace(&kt), show(&value, encoding));
     }
-    for (key, value) in system {
-        println!("  {MAGENTA}(system) {RESET}{BOLD}{key}{RESET}: {value}");
+    Outcome::Done
+}
+
+
+pub fn print_dump(
+    ctx: &Context, paths: &[&String], format: dump::DumpFormat, encoding: Encoding,
+    out: &mut Emitter
+) -> Status {
+    let mut status = Status::default();
+    if !out.human() {
+        for path in paths {
+            match dump::DumpEntry::read(ctx, path) {
+                Ok(entry) => {
+                    for (key, val) in entry.attrs {
+                        let key = key.to_string_lossy();
+                        let (key, kt) = split_key(&key);
+                        let codec = ctx.codec(key);
+                        out.emit(attr_record(path, key, kt, &val, encoding, codec));
+                    }
+                    status.add(Outcome::Done);
+                },
+                Err(err) => {
+                    out.emit(error_record(path, None, &err.to_string()));
+                    status.add(Outcome::Failed);
+                },
+            }
+        }
+        return status;
     }
-    for (key, value) in trusted {
-        println!("  {MAGENTA}(trusted) {RESET}{BOLD}{key}{RESET}: {value}");
+    if format == dump::DumpFormat::Reat {
+        println!("{}", dump::HEADER);
     }
-    for (key, value) in security {
-        println!("  {MAGENTA}(security) {RESET}{BOLD}{key}{RESET}: {value}");
+    for path in paths {
+        match dump::DumpEntry::read(ctx, path) {
+            Ok(entry) if entry.attrs.is_empty() => status.add(Outcome::Done),
+            Ok(entry) => {
+                print!("{}", entry.write(format));
+                status.add(Outcome::Done);
+            },
+            Err(err) => {
+                errln!(
+    "{BOLD}{GREEN}{path}{RESET}{RED}{BOLD}: could not {YELLOW}dump{RED}: {err}.{RESET}"
+                );
+                status.add(Outcome::Failed);
+            },
+        }
     }
+    status
 }
 
+/// How copy treats keys the destination has already.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Merge {
+    /// Replace the value of the destination.
+    #[default]
+    Overwrite,
+    /// Keep the value of the destination.
+    Keep,
+    /// Add the list items of the source the destination does not hold yet.
+    Union,
+}
 
-pub fn print_dump<P: AsRef<Path> + Display>(path: P) {
-    let Ok(xattrs) = xattr::list(&path)
-    else {
-        println!("{path}\nfail");
-        return;
-    };
-    if xattrs.clone().next().is_none() {
-        return;
+/// Which keys copy copies and how.
+#[derive(Clone, Debug, Default)]
+pub struct CopyOptions {
+    /// Only copy keys matching one of these, if there are any.
+    pub keys: Vec<Glob>,
+    /// Skip keys matching one of these.
+    pub nokeys: Vec<Glob>,
+    /// Only copy keys in the `user` namespace.
+    pub user: bool,
+    pub merge: Merge,
+}
+
+impl CopyOptions {
+    // Whether `key` is selected by `keys` and `nokeys`, regardless of its namespace.
+    fn copies(&self, key: &OsStr) -> bool {
+        let key = key.to_string_lossy();
+        // User keys are matched without `user.`, others with their namespace.
+        let key = key.strip_prefix("user.").unwrap_or(&key);
+        (self.keys.is_empty() || self.keys.iter().any(|glob| glob.matches(key)))
+            && !self.nokeys.iter().any(|glob| glob.matches(key))
     }
-    println!("{path}");
-    let mut list = Vec::new();
-    let mut dropped = 0;
-    for attr in xattrs {
-        match get_osstr(&path, &attr) {
-            Some(((key, KeyType::User), value)) => list.push((key, value)),
-            Some(((_, KeyType::System | KeyType::Trusted | KeyType::Security), _)) => dropped += 1,
-            None => { },
+}
+
+// The list in `dst` followed by the items of the list in `src` it does not hold yet.
+fn union(dst: &[u8], src: &[u8], codec: ListCodec) -> Option<Vec<u8>> {
+    let (dst, src) = (std::str::from_utf8(dst).ok()?, std::str::from_utf8(src).ok()?);
+    let mut list = codec.decode(dst);
+    let len = list.len();
+    for item in codec.decode(src) {
+        if !list.contains(&item) {
+            list.push(item);
         }
     }
-    for (key, val) in &list {
-        print!("{} ", key.chars().filter(|c| *c == '\n').count() + 1);
-        print!("{} ", val.chars().filter(|c| *c == '\n').count() + 1);
+    if list.len() == len {
+        return Some(dst.as_bytes().to_vec());
     }
-    println!("{dropped}");
-    for (key, val) in list {
-        println!("{key}");
-        println!("{val}");
+    Some(codec.encode(&list).into_bytes())
+}
+
+pub fn print_copy<P: AsRef<Path> + Display>(
+    ctx: &Context, srcp: P, dstp: P, print_filename: bool, options: &CopyOptions,
+    protect: &Protections
+) -> Outcome {
+    let pre = prefix(&dstp, print_filename);
+    let Some((copied, _, skipped)) = copy_attrs(ctx, &srcp, &dstp, &pre, options, protect)
+    else {
+        return Outcome::Failed;
+    };
+    for key in skipped {
+        errln!(
+"{pre}{YELLOW}Skipped{RESET} attribute {BOLD}{key}{RESET}, it is outside the user namespace and needs {YELLOW}force{RESET}."
+        );
     }
+    outln!(
+"{pre}{GREEN}Successfully {YELLOW}copied{GREEN} {DEFAULT}{copied}{GREEN} attributes from {DEFAULT}{srcp}{GREEN}.{RESET}"
+    );
+    Outcome::Done
 }
 
-pub fn print_copy<P: AsRef<Path> + Display>(srcp: P, dstp: P) {
-    let Ok(xattrs ) = xattr::list(&srcp)
+// Copy the attributes of `srcp` to `dstp`. Returns how many were copied, the keys that both
+// had with different values and the keys left out for being outside the user namespace,
+// or nothing if anything failed.
+fn copy_attrs<P: AsRef<Path> + Display>(
+    ctx: &Context, srcp: P, dstp: P, pre: &str, options: &CopyOptions, protect: &Protections
+) -> Option<(usize, Vec<String>, Vec<String>)> {
+    let Ok(xattrs ) = list(ctx, &srcp)
     else {
-        println!(
+        errln!(
     "{BOLD}{GREEN}{srcp}{RESET}{RED}{BOLD}: could not {YELLOW}copy{RED} from attributes.{RESET}"
         );
-        return;
+        return None;
     };
     let mut ok = true;
+    let mut copied = 0;
+    let mut conflicts = Vec::new();
+    let mut skipped = Vec::new();
     for key in xattrs {
-        let val = xattr::get(&srcp, &key);
-        if let Ok(Some(val)) = val && xattr::set(&dstp, &key, &val).is_err() {
+        if !options.copies(&key) {
+            continue;
+        }
+        if options.user && !key.as_bytes().starts_with(b"user.") {
+            skipped.push(key.to_string_lossy().into_owned());
+            continue;
+        }
+        let name = key.to_string_lossy();
+        let name = name.strip_prefix("user.").unwrap_or(&name);
+        let Ok(val) = get_raw_osstr(ctx, &srcp, &key) else { continue };
+        let old = get_raw_osstr(ctx, &dstp, &key).ok();
+        if old.as_ref().is_some_and(|old| *old != val) {
+            conflicts.push(name.to_string());
+        }
+        let new = match (options.merge, &old) {
+            (Merge::Keep, Some(_)) => continue,
+            (Merge::Union, Some(old)) => match union(old, &val, ctx.codec(name)) {
+                Some(new) => new,
+                None => {
+                    ok = false;
+                    errln!(
+"{pre}{BOLD}{RED}Could not {YELLOW}union{RED} attribute {DEFAULT}{name}{RED}, it is not a list.{RESET}"
+                    );
+                    continue;
+                },
+            },
+            _ => val,
+        };
+        if old.as_ref() == Some(&new) {
+            continue;
+        }
+        let edit = options.merge == Merge::Union;
+        let qualified = key.to_string_lossy();
+        if !allowed(protect, pre, "copy", &qualified, old.as_deref(), Some(&new), edit) {
+            ok = false;
+            continue;
+        }
+        if set_osstr(ctx, &dstp, &key, &new, false).is_err() {
             ok = false;
-            println!(
-"{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{key:?}{RED} on destination.{RESET}",
+            errln!(
+"{pre}{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{name}{RED} on destination.{RESET}"
+            );
+        } else {
+            copied += 1;
+        }
+    }
+    ok.then_some((copied, conflicts, skipped))
+}
+
+/// Copy the `user` attributes of every file in the tree at `src` to its counterpart in the tree
+/// at `dst`, then summarize the files without counterparts and the conflicts.
+pub fn print_sync(
+    ctx: &Context, src: &str, dst: &str, walker: &Walker, by: Counterpart, options: &CopyOptions,
+    protect: &Protections
+) -> Status {
+    let mut status = Status::default();
+    let pairing = mirror::pair(walker, src, dst, by);
+    for (path, err) in &pairing.errors {
+        errln!(
+    "{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: could not {YELLOW}sync{RED}: {err}.{RESET}",
+            path.display()
+        );
+        status.add(Outcome::Failed);
+    }
+    let (mut copied, mut conflicts) = (0, 0);
+    for (srcp, dstp) in &pairing.pairs {
+        let (Some(srcp), Some(dstp)) = (srcp.to_str(), dstp.to_str()) else {
+            errln!(
+"{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: skipped, path is not valid {YELLOW}UTF-8{RED}.{RESET}",
+                srcp.display()
             );
+            status.add(Outcome::Failed);
+            continue;
+        };
+        let pre = prefix(dstp, true);
+        match copy_attrs(ctx, srcp, dstp, &pre, options, protect) {
+            Some((n, keys, _)) => {
+                copied += n;
+                conflicts += keys.len();
+                for key in keys {
+                    let resolution = match options.merge {
+                        Merge::Overwrite => "overwritten",
+                        Merge::Keep => "kept",
+                        Merge::Union => "merged",
+                    };
+                    outln!(
+                "{pre}{YELLOW}conflict{RESET} in {BOLD}{key}{RESET}, destination {resolution}"
+                    );
+                }
+                status.add(Outcome::Done);
+            },
+            None => status.add(Outcome::Failed),
         }
     }
-    if ok {
-        println!("{BOLD}{GREEN}Successfully copied from source to destination.{RESET}");
+    for path in &pairing.missing_dst {
+        outln!("{}{YELLOW}no counterpart{RESET} in destination", prefix(path.display(), true));
+        status.add(Outcome::Unmatched);
     }
+    for path in &pairing.missing_src {
+        outln!("{}{YELLOW}no counterpart{RESET} in source", prefix(path.display(), true));
+    }
+    for (srcs, dsts) in &pairing.ambiguous {
+        let list = |paths: &[PathBuf]| paths.iter()
+            .map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+        errln!(
+"{BOLD}{RED}Ambiguous {YELLOW}counterparts{RED}, source {DEFAULT}{}{RED} and destination {DEFAULT}{}{RED}.{RESET}",
+            list(srcs), list(dsts)
+        );
+        status.add(Outcome::Failed);
+    }
+    outln!(
+        "{GREEN}{BOLD}synced{RESET}{BOLD}:{RESET} {} pairs, {copied} attributes copied, \
+{conflicts} conflicts, {} missing in destination, {} missing in source, {} ambiguous",
+        pairing.pairs.len(), pairing.missing_dst.len(), pairing.missing_src.len(),
+        pairing.ambiguous.len()
+    );
+    status
 }
 
-pub fn print_get<P: AsRef<Path> + Display>(path: P, key: &str, print_filename: bool, verbose: bool) {
-    if let Some(((key, ktype), value)) = get(&path, key) {
-        if print_filename {
-            print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+pub fn print_get<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, key: &str, print_filename: bool, verbose: bool, encoding: Encoding,
+    out: &mut Emitter
+) -> Outcome {
+    let res = get(ctx, &path, key);
+    let outcome = match &res {
+        Ok(_) => Outcome::Done,
+        Err(ReatError::NotFound) => Outcome::Unmatched,
+        Err(_) => Outcome::Failed,
+    };
+    if !out.human() {
+        match res {
+            Ok(((key, kt), value)) => {
+                let codec = ctx.codec(&key);
+                out.emit(attr_record(&path.to_string(), &key, kt, &value, encoding, codec));
+            },
+            Err(err) => out.emit(error_record(&path.to_string(), Some(key), &err.to_string())),
         }
-        match ktype {
-            KeyType::User => { },
-            KeyType::System => print!("{MAGENTA}(system) {RESET}"),
-            KeyType::Trusted => print!("{MAGENTA}(trusted) {RESET}"),
-            KeyType::Security => print!("{MAGENTA}(security) {RESET}"),
+        return outcome;
+    }
+    if let Ok(((key, ktype), value)) = &res {
+        if print_filename {
+            out!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
         }
-        println!("{BOLD}{key}{RESET}: {value}");
+        outln!("{}{BOLD}{key}{RESET}: {}", namespace(ktype), show(value, encoding));
+    } else if let Err(err @ ReatError::PermissionDenied(_)) = &res {
+        let pre = prefix(&path, print_filename);
+        errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}get{RED} attribute {DEFAULT}{key}{RED}: {err}.{RESET}"
+        );
     } else if !print_filename {
-        println!("{BOLD}{RED}Could not {YELLOW}get{RED} attribute {DEFAULT}{key}{RED}.{RESET}");
+        errln!("{BOLD}{RED}Could not {YELLOW}get{RED} attribute {DEFAULT}{key}{RED}.{RESET}");
     } else if verbose {
-        println!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET}{BOLD}{key}{RESET}: {RED} ❌{RESET}");
+        outln!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET}{BOLD}{key}{RESET}: {RED} ❌{RESET}");
     }
+    outcome
 }
 
 pub fn print_set<P: AsRef<Path> + Display>(
-    path: P, key: &str, value: &str, print_filename: bool, force: bool
-) {
-    if print_filename {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+    ctx: &Context, path: P, key: &str, value: &str, print_filename: bool, protect: &Protections
+) -> Outcome {
+    let pre = prefix(&path, print_filename);
+    let old = get(ctx, &path, key).ok().map(|(_, old)| old);
+    let qualified = qualify(key);
+    if !allowed(protect, &pre, "set", &qualified, old.as_deref(), Some(value.as_bytes()), false) {
+        return Outcome::Failed;
     }
-    match set(path, key, value, key == "tags" && !force) {
-        Ok(Some(old)) => println!(
-            "{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}overwritten{GREEN} successfully.
-  Old value was \"{RESET}{old}{GREEN}\".{RESET}"
-        ),
-        Ok(None) => println!(
-            "{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}set{GREEN} successfully.{RESET}"
+    let res = set(ctx, path, key, value.as_bytes(), false);
+    match &res {
+        Ok(Some(old)) => outln!(
+            "{pre}{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}overwritten{GREEN} successfully.
+  Old value was \"{RESET}{}{GREEN}\".{RESET}",
+            show(old, Encoding::default())
         ),
-        Err(true) => println!(
-    "{BOLD}{RED}Could not {YELLOW}set{RED} {DEFAULT}tags{RED} without {YELLOW}force{RED}!{RESET}"
+        Ok(None) => outln!(
+            "{pre}{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}set{GREEN} successfully.{RESET}"
         ),
-        Err(_) => println!(
-            "{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{key}{RED}.{RESET}"
+        Err(err) => errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{key}{RED}: {err}.{RESET}"
         ),
     }
+    if res.is_ok() { Outcome::Done } else { Outcome::Failed }
 }
 
-pub fn print_add_list<P: AsRef<Path> + Display>(path: P, key: &str, value: &str, print_filename: bool) {
-    if print_filename {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+pub fn print_add_list<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, key: &str, value: &str, print_filename: bool, options: ListOptions,
+    protect: &Protections
+) -> Outcome {
+    let pre = prefix(&path, print_filename);
+    if !editable(ctx, protect, &pre, "add", &path, key, options.sorted) {
+        return Outcome::Failed;
     }
-    match add_list(path, key, value) {
-        Ok(_) => println!(
-            "{YELLOW}Added{GREEN} list item to {DEFAULT}{key}{GREEN} successfully.{RESET}"
+    let res = add_list(ctx, path, key, &ctx.codec(key).decode(value), options);
+    match res {
+        Ok(ListChange { added: 0, reordered: false, .. }) => outln!(
+            "{pre}{GREEN}No {YELLOW}add{GREEN} required, {DEFAULT}{key}{GREEN} holds the items.{RESET}"
         ),
-        Err(_) => println!(
-            "{BOLD}{RED}Could not {YELLOW}add{RED} to attribute {DEFAULT}{key}{RED}.{RESET}"
+        Ok(ListChange { added: 0, .. }) => outln!(
+            "{pre}{GREEN}Items were in {DEFAULT}{key}{GREEN}, {YELLOW}sorted{GREEN} the list.{RESET}"
+        ),
+        Ok(ListChange { added, reordered, .. }) => {
+            if added == 1 {
+                out!("{pre}{YELLOW}Added{GREEN} list item to {DEFAULT}{key}{GREEN}");
+            } else {
+                out!(
+                    "{pre}{YELLOW}Added{GREEN} {DEFAULT}{added}{GREEN} list items to {DEFAULT}{key}{GREEN}"
+                );
+            }
+            if reordered {
+                outln!(" and {YELLOW}sorted{GREEN} the list.{RESET}");
+            } else {
+                outln!(" successfully.{RESET}");
+            }
+        },
+        Err(_) => errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}add{RED} to attribute {DEFAULT}{key}{RED}.{RESET}"
         ),
     }
+    if res.is_ok() { Outcome::Done } else { Outcome::Failed }
 }
 
-pub fn print_remove<P: AsRef<Path> + Display>(path: P, key: &str, print_filename: bool, force: bool) {
-    if print_filename {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+pub fn print_dedup<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, key: &str, print_filename: bool, verbose: bool, sorted: bool,
+    protect: &Protections
+) -> Outcome {
+    if !editable(ctx, protect, &prefix(&path, print_filename), "dedup", &path, key, true) {
+        return Outcome::Failed;
     }
-    if key == "tags" && !force {
-        println!(
-    "{BOLD}{RED}Could not {YELLOW}remove{RED} {DEFAULT}tags{RED} without {YELLOW}force{RED}!{RESET}"
-        );
-        return;
+    let res = dedup_list(ctx, &path, key, sorted);
+    let required = match &res {
+        Ok(change) => change.changed(),
+        Err(ReatError::NotFound) => false,
+        Err(_) => true,
+    };
+    let pre = prefix(&path, print_filename && (required || verbose));
+    match &res {
+        Ok(ListChange { removed: 0, reordered: true, .. }) => outln!(
+            "{pre}{YELLOW}Sorted{GREEN} {DEFAULT}{key}{GREEN}.{RESET}"
+        ),
+        Ok(change) if change.changed() => {
+            out!(
+                "{pre}{YELLOW}Removed{GREEN} {DEFAULT}{}{GREEN} duplicates from {DEFAULT}{key}{GREEN}",
+                change.removed
+            );
+            if change.reordered {
+                out!(" and {YELLOW}sorted{GREEN} the list");
+            }
+            outln!(".{RESET}");
+        },
+        Err(err) if required => errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}dedup{RED} attribute {DEFAULT}{key}{RED}: {err}.{RESET}"
+        ),
+        _ if verbose || !print_filename => outln!(
+            "{pre}{GREEN}No {YELLOW}dedup{GREEN} required.{RESET}"
+        ),
+        _ => { },
+    }
+    if required && res.is_err() { Outcome::Failed } else { Outcome::Done }
+}
+
+pub fn print_remove<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, key: &str, print_filename: bool, protect: &Protections
+) -> Outcome {
+    let pre = prefix(&path, print_filename);
+    let old = get(ctx, &path, key).ok().map(|(_, old)| old);
+    if !allowed(protect, &pre, "remove", &qualify(key), old.as_deref(), None, false) {
+        return Outcome::Failed;
     }
-    match remove(path, key) {
-        Ok(Some(old)) => println!(
-            "{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.
-  Old value was \"{RESET}{old}{GREEN}\".{RESET}"
+    let res = remove(ctx, path, key);
+    match &res {
+        Ok(Some(old)) => outln!(
+            "{pre}{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.
+  Old value was \"{RESET}{}{GREEN}\".{RESET}",
+            show(old, Encoding::default())
         ),
-        Ok(None) => println!(
-            "{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.{RESET}"
+        Ok(None) => outln!(
+            "{pre}{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.{RESET}"
         ),
-        Err(_) => println!(
-            "{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{key}{RED}.{RESET}"
+        Err(err) => errln!(
+        "{pre}{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{key}{RED}: {err}.{RESET}"
         ),
     }
+    if res.is_ok() { Outcome::Done } else { Outcome::Failed }
 }
 
 pub fn print_clear<P: AsRef<Path> + Display>(
-    path: P, print_filename: bool, verbose: bool, force: bool
-) {
+    ctx: &Context, path: P, print_filename: bool, verbose: bool, protect: &Protections
+) -> Outcome {
     let fn_msg = format!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET}");
-    let Ok(xattrs) = xattr::list(&path)
+    let pre = prefix(&path, print_filename);
+    let Ok(xattrs) = list(ctx, &path)
     else {
-        if print_filename { print!("{fn_msg} "); }
-        println!("{BOLD}{RED}Could not {YELLOW}clear{RED} attributes.{RESET}");
-        return;
+        errln!("{pre}{BOLD}{RED}Could not {YELLOW}clear{RED} attributes.{RESET}");
+        return Outcome::Failed;
     };
     if xattrs.clone().next().is_none() {
         if verbose {
-            println!("{fn_msg} {RED}{BOLD}❌{RESET}");
+            outln!("{fn_msg} {RED}{BOLD}❌{RESET}");
         }
-        return;
+        return Outcome::Done;
     }
+    let mut outcome = Outcome::Done;
     let mut list = Vec::new();
     let mut printed_fn = false;
     for attr in xattrs {
-        match get_osstr(&path, &attr) {
-            Some(((key, KeyType::User), _)) => list.push(key),
-            Some(_) if verbose && print_filename => {
-                if printed_fn {
-                    println!("{fn_msg}");
-                    printed_fn = true;
-                }
-                println!(
-                    "  {RED}{BOLD}cannot {YELLOW}clear{RED} non user attribute!{RESET}"
-                );
-            },
+        match read_attr(ctx, &path, &attr) {
+            Ok(((key, KeyType::User), value)) => list.push((attr, key, value)),
+            Ok(_) if verbose && print_filename => errln!(
+                "{pre}{RED}{BOLD}cannot {YELLOW}clear{RED} non user attribute!{RESET}"
+            ),
             _ => { },
         }
     }
-    for key in list {
-        let tags_protected = key == "tags" && !force;
-        if print_filename && tags_protected && !printed_fn {
-            println!("{fn_msg}  ");
-            printed_fn = true;
-        }
-        if tags_protected {
-            println!(
-"  {BOLD}{RED}Could not {YELLOW}remove{RED} {DEFAULT}tags{RED} without {YELLOW}force{RED}!{RESET}"
-            );
+    for (attr, key, value) in list {
+        if !allowed(protect, &pre, "remove", &attr.to_string_lossy(), Some(&value), None, false) {
+            outcome = Outcome::Failed;
             continue;
         }
-        let res = remove(&path, &key);
-        if (res.is_err() || verbose) && !printed_fn && print_filename {
-            println!("{fn_msg}");
+        let res = remove_osstr(ctx, &path, &attr);
+        if res.is_ok() && verbose && !printed_fn && print_filename {
+            outln!("{fn_msg}");
             printed_fn = true;
         }
         match res {
-            Ok(Some(old)) if verbose => println!(
+            Ok(Some(old)) if verbose => outln!(
                 "  {GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.
-    Old value was \"{RESET}{old}{GREEN}\".{RESET}"
+    Old value was \"{RESET}{}{GREEN}\".{RESET}",
+                show(&old, Encoding::default())
             ),
-            Ok(None) if verbose => println!(
+            Ok(None) if verbose => outln!(
                 "{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}removed{GREEN} successfully.{RESET}"
             ),
-            Err(_) => println!(
-                "{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{key}{RED}.{RESET}"
-            ),
+            Err(_) => {
+                errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{key}{RED}.{RESET}"
+                );
+                outcome = Outcome::Failed;
+            },
             _ => { },
         }
     }
+    outcome
 }
 
 
 pub fn print_cut_list<P: AsRef<Path> + Display>(
-    path: P, key: &str, value: &str, print_filename: bool, verbose: bool
-) {
-    let res = cut_list(&path, key, value);
-    if print_filename && (res.is_some() || verbose) {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+    ctx: &Context, path: P, key: &str, value: &str, print_filename: bool, verbose: bool,
+    protect: &Protections
+) -> Outcome {
+    if !editable(ctx, protect, &prefix(&path, print_filename), "cut", &path, key, true) {
+        return Outcome::Failed;
     }
-    match res {
-        Some(true) => println!(
-            "{GREEN}Successfully {YELLOW}cut{GREEN} {DEFAULT}{value}{GREEN} from {DEFAULT}{key}{GREEN}.{RESET}"
+    let res = cut_list(ctx, &path, key, &ctx.codec(key).decode(value));
+    let required = !matches!(res, Ok(false) | Err(ReatError::NotFound));
+    let pre = prefix(&path, print_filename && (required || verbose));
+    match &res {
+        Ok(true) => outln!(
+            "{pre}{GREEN}Successfully {YELLOW}cut{GREEN} {DEFAULT}{value}{GREEN} from {DEFAULT}{key}{GREEN}.{RESET}"
         ),
-        Some(false) => println!(
-            "{BOLD}{RED}Could not {YELLOW}cut{RED} {DEFAULT}{value}{RED} from {DEFAULT}{key}{RED}.{RESET}"
+        Err(err) if required => errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}cut{RED} {DEFAULT}{value}{RED} from {DEFAULT}{key}{RED}: {err}.{RESET}"
         ),
-        None if verbose || !print_filename => println!(
-            "{GREEN}No {YELLOW}cut{GREEN} required.{RESET}"
+        _ if verbose || !print_filename => outln!(
+            "{pre}{GREEN}No {YELLOW}cut{GREEN} required.{RESET}"
         ),
-        None => { },
+        _ => { },
+    }
+    if required && res.is_err() { Outcome::Failed } else { Outcome::Done }
+}
+
+pub fn print_contains(
+    ctx: &Context, mode: char, key: &str, values: &[Matcher], path: &str, index: Option<&Index>,
+    out: &mut Emitter
+) -> Outcome {
+    match contains(ctx, mode, key, values, path, index) {
+        Ok(true) => {
+            out.path(path);
+            Outcome::Done
+        },
+        Ok(false) => Outcome::Unmatched,
+        Err(err) => read_failed(path, "match", &err),
     }
 }
 
-pub fn print_contains(mode: char, key: &str, values: &[&String], path: &str) {
+fn contains(
+    ctx: &Context, mode: char, key: &str, values: &[Matcher], path: &str, index: Option<&Index>
+) -> Result<bool, ReatError> {
     let blanket = values.is_empty();
-    if let Some((_, avalue)) = get(path, key) {
-        let list = avalue.split(',').collect::<Vec<_>>();
+    if let Some(avalue) = lookup(ctx, index, path, key)? {
+        let list = ctx.codec(key).decode(&avalue);
         if blanket && (mode == 'o' || mode == 'a') {
-            println!("{path}");
+            return Ok(true);
         } else if mode == 'o' {
-            'outer: for item in list {
+            for item in &list {
                 for value in values {
-                    if item.contains(*value) {
-                        println!("{path}");
-                        break 'outer;
+                    if value.matches(item) {
+                        return Ok(true);
                     }
                 }
             }
@@ -296,7 +851,7 @@ pub fn print_contains(mode: char, key: &str, values: &[&String], path: &str) {
             for value in values {
                 let mut lok = false;
                 for item in &list {
-                    if item.contains(*value) {
+                    if value.matches(item) {
                         lok = true;
                         break;
                     }
@@ -306,182 +861,189 @@ pub fn print_contains(mode: char, key: &str, values: &[&String], path: &str) {
                     break;
                 }
             }
-            if ok {
-                println!("{path}");
-            }
+            return Ok(ok);
         } else if mode == 'n' && !blanket {
             let mut ok = true;
             'outer: for value in values {
                 for item in &list {
-                    if item.contains(*value) {
+                    if value.matches(item) {
                         ok = false;
                         break 'outer;
                     }
                 }
             }
-            if ok {
-                println!("{path}");
-            }
+            return Ok(ok);
         }
     } else if blanket && mode == 'n' {
-        println!("{path}");
+        return Ok(true);
     }
+    Ok(false)
 }
 
-pub fn print_rename<P: AsRef<Path> + Display>(
-    path: P, old_att_name: &str, new_att_name: &str, print_filename: bool, force: bool
-) {
-    if print_filename {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+pub fn print_query(
+    ctx: &Context, query: &Query, path: &str, index: Option<&Index>, out: &mut Emitter
+) -> Outcome {
+    let failure = std::cell::RefCell::new(None);
+    let items = |key: &str| match lookup(ctx, index, path, key) {
+        Ok(value) => value.map(|value| ctx.codec(key).decode(&value)),
+        Err(err) => {
+            failure.borrow_mut().get_or_insert(err);
+            None
+        },
+    };
+    let matched = query.eval(&items);
+    if let Some(err) = failure.into_inner() {
+        return read_failed(path, "query", &err);
     }
-    if let Some((_, value)) = get(&path, old_att_name) {
-        match set(&path, new_att_name, &value, !force) {
-            Ok(Some(old_val)) => println!(
-                "{GREEN}Old value was \"{RESET}{old_val}{GREEN}\".{RESET}"
+    if !matched {
+        return Outcome::Unmatched;
+    }
+    out.path(path);
+    Outcome::Done
+}
+
+pub fn print_rename<P: AsRef<Path> + Display>(
+    ctx: &Context, path: P, old_att_name: &str, new_att_name: &str, print_filename: bool,
+    force: bool, protect: &Protections
+) -> Outcome {
+    let pre = prefix(&path, print_filename);
+    if let Ok((_, value)) = get(ctx, &path, old_att_name) {
+        let old_val = get(ctx, &path, new_att_name).ok().map(|(_, old_val)| old_val);
+        let (old_key, new_key) = (qualify(old_att_name), qualify(new_att_name));
+        let allow = allowed(protect, &pre, "remove", &old_key, Some(&value), None, false)
+            && allowed(protect, &pre, "set", &new_key, old_val.as_deref(), Some(&value), false);
+        if !allow {
+            return Outcome::Failed;
+        }
+        match set(ctx, &path, new_att_name, &value, !force) {
+            Ok(Some(old_val)) => outln!(
+                "{pre}{GREEN}Old value was \"{RESET}{}{GREEN}\".{RESET}",
+                show(&old_val, Encoding::default())
             ),
             Ok(None) => { },
-            Err(true) => {
-                println!(
-"{BOLD}{RED}Could not {YELLOW}set{RED} {DEFAULT}{new_att_name}{RED} without {YELLOW}force{RED}!{RESET}"
+            Err(ReatError::Protected(_)) => {
+                errln!(
+"{pre}{BOLD}{RED}Could not {YELLOW}set{RED} {DEFAULT}{new_att_name}{RED} without {YELLOW}force{RED}!{RESET}"
                 );
-                return;
+                return Outcome::Failed;
             },
             Err(_) => {
-                println!(
-            "{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{new_att_name}{RED}.{RESET}"
+                errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{new_att_name}{RED}.{RESET}"
                 );
-                return;
+                return Outcome::Failed;
             },
         }
-        if remove(&path, old_att_name).is_err() {
-            println!(
-        "{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{old_att_name}{RED}.{RESET}"
+        if remove(ctx, &path, old_att_name).is_err() {
+            errln!(
+        "{pre}{BOLD}{RED}Could not {YELLOW}remove{RED} attribute {DEFAULT}{old_att_name}{RED}.{RESET}"
             );
+            Outcome::Failed
         } else {
-            println!(
-"{GREEN}Successfully {YELLOW}renamed{GREEN} attribute {DEFAULT}{old_att_name}{GREEN} to {DEFAULT}{new_att_name}{GREEN}.{RESET}"
+            outln!(
+"{pre}{GREEN}Successfully {YELLOW}renamed{GREEN} attribute {DEFAULT}{old_att_name}{GREEN} to {DEFAULT}{new_att_name}{GREEN}.{RESET}"
             );
+            Outcome::Done
         }
     } else {
-        println!(
-            "{BOLD}{RED}Could not {YELLOW}get{RED} attribute {DEFAULT}{old_att_name}{RED}.{RESET}"
+        errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}get{RED} attribute {DEFAULT}{old_att_name}{RED}.{RESET}"
         );
+        Outcome::Failed
     }
 }
 
+/// The list item `replace` looks for and the item it puts in its place.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub struct Replacement<'a> {
+    pub old: &'a str,
+    pub new: &'a str,
+}
+
 pub fn print_replace<P: AsRef<Path> + Display>(
-    path: P, key: &str, old_val_name: &str, new_val_name: &str, print_filename: bool, verbose: bool
-) {
-    let res = replace_list(&path, key, old_val_name, new_val_name);
-    if print_filename && (res.is_some() || verbose) {
-        print!("{BOLD}{GREEN}{path}{RESET}{GREEN}:{RESET} ");
+    ctx: &Context, path: P, key: &str, replacement: Replacement, print_filename: bool,
+    verbose: bool, protect: &Protections
+) -> Outcome {
+    let Replacement { old: old_val_name, new: new_val_name } = replacement;
+    if !editable(ctx, protect, &prefix(&path, print_filename), "replace", &path, key, true) {
+        return Outcome::Failed;
     }
-    match res {
-        Some(true) => println!(
-            "{GREEN}Successfully {YELLOW}replaced{GREEN} {DEFAULT}{old_val_name}{GREEN} with {DEFAULT}{new_val_name}{GREEN} from {DEFAULT}{key}{GREEN}.{RESET}"
+    let res = replace_list(ctx, &path, key, old_val_name, new_val_name);
+    let required = !matches!(res, Ok(false) | Err(ReatError::NotFound));
+    let pre = prefix(&path, print_filename && (required || verbose));
+    match &res {
+        Ok(true) => outln!(
+            "{pre}{GREEN}Successfully {YELLOW}replaced{GREEN} {DEFAULT}{old_val_name}{GREEN} with {DEFAULT}{new_val_name}{GREEN} from {DEFAULT}{key}{GREEN}.{RESET}"
         ),
-        Some(false) => println!(
-            "{BOLD}{RED}Could not {YELLOW}replace{RED} {DEFAULT}{old_val_name}{RED} from {DEFAULT}{key}{RED}.{RESET}"
+        Err(err) if required => errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}replace{RED} {DEFAULT}{old_val_name}{RED} from {DEFAULT}{key}{RED}: {err}.{RESET}"
         ),
-        None if verbose || !print_filename => println!(
-            "{GREEN}No {YELLOW}replacement{GREEN} required.{RESET}"
+        _ if verbose || !print_filename => outln!(
+            "{pre}{GREEN}No {YELLOW}replacement{GREEN} required.{RESET}"
         ),
-        None => { },
+        _ => { },
     }
+    if required && res.is_err() { Outcome::Failed } else { Outcome::Done }
 }
 
-pub fn print_restore(dump: &str, paths: &[&String], verbose: bool, force: bool) {
-    let check = !paths.is_empty();
-    let mut paths_set = HashSet::new();
-    if check {
-        for path in paths {
-            paths_set.insert(path.as_str());
-        }
-    }
-    let lines = dump.split('\n');
-    let mut mode = "f";
-    let mut file = "";
-    let mut nums = Vec::<usize>::new();
-    let mut nums_i = 0;
-    let mut key = String::new();
-    let mut val = String::new();
-    let mut kvs = Vec::new();
-    for line in lines {
-        if mode == "f" {
-            file = line;
-            mode = "n";
-        } else if mode == "n" {
-            nums.clear();
-            kvs.clear();
-            let nums_raw = line.split(' ');
-            for num in nums_raw {
-                let res = num.parse();
-                if let Ok(res) = res {
-                    nums.push(res);
-                }
-            }
-            nums.pop();
-            mode = "k";
-            nums_i = 0;
-        } else if mode == "k" {
-            if !key.is_empty() {
-                key.push('\n');
-            }
-            key.push_str(line);
-            nums[nums_i] -= 1;
-            if nums[nums_i] == 0 {
-                mode = "v";
-                nums_i += 1;
-            }
-        } else if mode == "v" {
-            if !val.is_empty() {
-                val.push('\n');
+pub fn print_restore(
+    ctx: &Context, dump: &str, paths: &[&String], verbose: bool, force: bool, protect: &Protections
+) -> Status {
+    let mut status = Status::default();
+    let entries = match dump::parse(dump) {
+        Ok(entries) => entries,
+        Err(err) => {
+            errln!("{BOLD}{RED}Could not {YELLOW}restore{RED}: {err}.{RESET}");
+            status.add(Outcome::Failed);
+            return status;
+        },
+    };
+    let paths_set = paths.iter().map(|path| OsStr::new(path.as_str())).collect::<HashSet<_>>();
+    for entry in entries {
+        if !paths_set.is_empty() && !paths_set.contains(entry.path.as_os_str()) {
+            continue;
+        }
+        let file = entry.path.to_string_lossy();
+        let pre = prefix(&file, true);
+        if verbose {
+            outln!("{BOLD}{GREEN}{file}{RESET}{GREEN}:{RESET}");
+        }
+        for (k, v) in &entry.attrs {
+            let old = get_raw_osstr(ctx, &entry.path, k).ok();
+            if !allowed(protect, &pre, "set", &k.to_string_lossy(), old.as_deref(), Some(v), false) {
+                status.add(Outcome::Failed);
+                continue;
             }
-            val.push_str(line);
-            nums[nums_i] -= 1;
-            if nums[nums_i] == 0 {
-                mode = "k";
-                nums_i += 1;
-                kvs.push((std::mem::take(&mut key), std::mem::take(&mut val)));
-                if nums_i >= nums.len() {
-                    mode = "f";
-                    if !check || paths_set.contains(file) {
-                        let mut printed = false;
-                        if verbose {
-                            println!("{BOLD}{GREEN}{file}{RESET}{GREEN}:{RESET}");
-                            printed = true;
-                        }
-                        for (k, v) in &kvs {
-                            let res = set(file, k, v, !force);
-                            if res.is_err() && !printed {
-                                println!("{BOLD}{GREEN}{file}{RESET}{GREEN}:{RESET}");
-                                printed = true;
-                            }
-                            match res {
-                                Ok(None) if verbose => println!(
+            let res = set_osstr(ctx, &entry.path, k, v, !force);
+            status.add(if res.is_ok() { Outcome::Done } else { Outcome::Failed });
+            let k = k.to_string_lossy();
+            match res {
+                Ok(None) if verbose => outln!(
     "  {GREEN}Attribute {DEFAULT}{k}{GREEN} {YELLOW}set{GREEN} successfully.{RESET}"
-                                ),
-                                Ok(Some(old)) if verbose => println!(
+                ),
+                Ok(Some(old)) if verbose => outln!(
     "  {GREEN}Attribute {DEFAULT}{k}{GREEN} {YELLOW}overwritten{GREEN} successfully.
-  Old value was \"{RESET}{old}{GREEN}\".{RESET}"
-                                ),
-                                Ok(_) => { },
-                                Err(true) => println!(
-    "  {BOLD}{RED}Could not {YELLOW}set{RED} {DEFAULT}{k}{RED} without {YELLOW}force{RED}!{RESET}"
-                                ),
-                                Err(false) => println!(
-    "  {BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{k}{RED}.{RESET}"
-                                ),
-                            }
-                        }
-                    }
-                }
+  Old value was \"{RESET}{}{GREEN}\".{RESET}",
+                    show(&old, Encoding::default())
+                ),
+                Ok(_) => { },
+                Err(ReatError::Protected(_)) => errln!(
+    "{pre}{BOLD}{RED}Could not {YELLOW}set{RED} {DEFAULT}{k}{RED} without {YELLOW}force{RED}!{RESET}"
+                ),
+                Err(err) => errln!(
+    "{pre}{BOLD}{RED}Could not {YELLOW}set{RED} attribute {DEFAULT}{k}{RED}: {err}.{RESET}"
+                ),
             }
         }
     }
+    status
 }
 
-pub fn print_rank(key: &str, paths: &[&String], flag_a: &str, flag_b: &str) {
+pub fn print_rank(
+    ctx: &Context, key: &str, paths: &[&String], flag_a: &str, flag_b: &str,
+    index: Option<&Index>, out: &mut Emitter
+) -> Status {
+    let mut status = Status::default();
     let mut counts = HashMap::new();
     let mut total = 0;
     let mut present = 0;
@@ -494,26 +1056,56 @@ pub fn print_rank(key: &str, paths: &[&String], flag_a: &str, flag_b: &str) {
     }
     for path in paths {
         total += 1;
-        if key.is_empty() {
-            if let Ok(xattrs) = xattr::list(path) {
-                for attr in xattrs {
-                    if let Some(((key, _), _)) = get_osstr(path, &attr) {
+        if key.is_empty() && let Some(entry) = index.and_then(|index| index.entry(ctx, path)) {
+            for (key, _) in &entry.attrs {
+                let key = key.to_string_lossy();
+                let key = key.strip_prefix("user.").unwrap_or(&key).to_string();
+                let count = counts.get(&key).unwrap_or(&0);
+                counts.insert(key, count + 1);
+            }
+            // Only user attributes are indexed, the others still come from the file.
+            for attr in list(ctx, path).into_iter().flatten() {
+                if !attr.to_string_lossy().starts_with("user.")
+                    && let Ok(((key, _), _)) = get_osstr(ctx, path, &attr)
+                {
+                    let count = counts.get(&key).unwrap_or(&0);
+                    counts.insert(key.clone(), count + 1);
+                }
+            }
+        } else if key.is_empty() {
+            match list(ctx, path) {
+                Ok(xattrs) => for attr in xattrs {
+                    if let Ok(((key, _), _)) = get_osstr(ctx, path, &attr) {
                         let count = counts.get(&key).unwrap_or(&0);
                         counts.insert(key.clone(), count + 1);
                     }
-                }
+                },
+                Err(err) => {
+                    status.add(read_failed(path, "rank", &err));
+                    continue;
+                },
             }
-        } else if let Some((_, avalue)) = get(path, key) {
-            present += 1;
-            let list = avalue.split(',').map(ToString::to_string).collect::<Vec<_>>();
-            for item in list {
-                let count = counts.get(&item).unwrap_or(&0);
-                counts.insert(item.clone(), count + 1);
+        } else {
+            match lookup(ctx, index, path, key) {
+                Ok(Some(avalue)) => {
+                    present += 1;
+                    let list = ctx.codec(key).decode(&avalue);
+                    for item in list {
+                        let count = counts.get(&item).unwrap_or(&0);
+                        counts.insert(item.clone(), count + 1);
+                    }
+                },
+                Ok(None) => { },
+                Err(err) => {
+                    status.add(read_failed(path, "rank", &err));
+                    continue;
+                },
             }
         }
+        status.add(Outcome::Done);
     }
-    if reverse {
-        println!("{GREEN}{BOLD}total{RESET}{BOLD}:{RESET} {present} / {total}");
+    if reverse && out.human() {
+        outln!("{GREEN}{BOLD}total{RESET}{BOLD}:{RESET} {present} / {total}");
     }
 
     fn do_reverse<T: std::cmp::Ord>(mut v: Vec<T>, reverse: bool) -> Vec<T> {
@@ -529,17 +1121,209 @@ pub fn print_rank(key: &str, paths: &[&String], flag_a: &str, flag_b: &str) {
     if flip {
         let res = do_reverse(counts.iter().collect::<Vec<_>>(), reverse);
         for (item, freq) in res {
-            println!("{BOLD}{item}{RESET}: {freq}");
+            rank_line(key, item, *freq, out);
         }
     } else {
         let res = counts.iter().map(|(k, f)| (f, k)).collect::<Vec<_>>();
         let res = do_reverse(res, reverse);
         for (freq, item) in res {
-            println!("{BOLD}{item}{RESET}: {freq}");
+            rank_line(key, item, *freq, out);
+        }
+    }
+
+    if !out.human() {
+        out.emit(Json::object([
+            ("key", key.into()), ("present", present.into()), ("total", total.into()),
+        ]));
+    } else if !reverse {
+        outln!("{GREEN}{BOLD}total{RESET}{BOLD}:{RESET} {present} / {total}");
+    }
+    status
+}
+
+/// Print `paths` ordered by the value of `key`. Files that can not be read are left out.
+pub fn print_sort(
+    ctx: &Context, key: &str, paths: &[&String], index: Option<&Index>, options: SortOptions,
+    out: &mut Emitter
+) -> Status {
+    let mut status = Status::default();
+    let mut items = Vec::new();
+    for path in paths {
+        match lookup(ctx, index, path, key) {
+            Ok(value) => items.push((*path, value)),
+            Err(err) => status.add(read_failed(path, "sort", &err)),
+        }
+    }
+    let sorted = sort::sort_by_value(items, options);
+    for path in &sorted {
+        out.path(path);
+    }
+    if !sorted.is_empty() {
+        status.add(Outcome::Done);
+    } else if status.failed == 0 {
+        status.add(Outcome::Unmatched);
+    }
+    status
+}
+
+fn rank_line(key: &str, item: &str, count: usize, out: &mut Emitter) {
+    if out.human() {
+        outln!("{BOLD}{item}{RESET}: {count}");
+    } else {
+        out.emit(Json::object([
+            ("key", key.into()), ("item", item.into()), ("count", count.into()),
+        ]));
+    }
+}
+
+/// Revert transaction `id` of the journal, or the last one that was not undone.
+/// Attributes that changed since are skipped without force. Every change is reverted on the
+/// file it was made on, following symlinks or not like the change did.
+/// Returns the id of the reverted transaction.
+pub fn print_undo(
+    ctx: &mut Context, journal: &Journal, id: Option<u64>, verbose: bool, force: bool,
+    protect: &Protections
+) -> (Status, Option<u64>) {
+    let mut status = Status::default();
+    let transaction = match id {
+        Some(id) => journal.get(id),
+        None => journal.last(),
+    };
+    let Some(transaction) = transaction else {
+        match id {
+            Some(id) => errln!("{BOLD}{RED}No {YELLOW}transaction{RED} {DEFAULT}{id}{RED}.{RESET}"),
+            None => errln!("{BOLD}{RED}Nothing to {YELLOW}undo{RED}.{RESET}"),
+        }
+        status.add(Outcome::Failed);
+        return (status, None);
+    };
+    let id = transaction.id;
+    if journal.undone(id) && !force {
+        errln!(
+"{BOLD}{RED}Transaction {DEFAULT}{id}{RED} was undone already, {YELLOW}undo{RED} it again with {YELLOW}force{RED}.{RESET}"
+        );
+        status.add(Outcome::Failed);
+        return (status, None);
+    }
+    let follow = ctx.follow;
+    for change in transaction.changes.iter().rev() {
+        ctx.follow = change.follow;
+        let path = change.path.to_string_lossy();
+        let key = change.key.to_string_lossy();
+        let pre = prefix(&path, true);
+        let current = get_raw_osstr(ctx, &change.path, &change.key).ok();
+        if current != change.new && !force {
+            errln!(
+"{pre}{BOLD}{RED}Attribute {DEFAULT}{key}{RED} changed since, not reverted without {YELLOW}force{RED}.{RESET}"
+            );
+            status.add(Outcome::Failed);
+            continue;
+        }
+        let verb = if change.old.is_some() { "revert" } else { "remove" };
+        let old = change.old.as_deref();
+        if !allowed(protect, &pre, verb, &key, current.as_deref(), old, false) {
+            status.add(Outcome::Failed);
+            continue;
+        }
+        let res = match &change.old {
+            Some(old) => set_osstr(ctx, &change.path, &change.key, old, false).map(|_| ()),
+            None if current.is_none() => Ok(()),
+            None => remove_osstr(ctx, &change.path, &change.key).map(|_| ()),
+        };
+        match res {
+            Ok(()) => {
+                if verbose {
+                    outln!(
+                "{pre}{GREEN}Attribute {DEFAULT}{key}{GREEN} {YELLOW}reverted{GREEN}.{RESET}"
+                    );
+                }
+                status.add(Outcome::Done);
+            },
+            Err(err) => {
+                errln!(
+            "{pre}{BOLD}{RED}Could not {YELLOW}revert{RED} attribute {DEFAULT}{key}{RED}: {err}.{RESET}"
+                );
+                status.add(Outcome::Failed);
+            },
+        }
+    }
+    ctx.follow = follow;
+    outln!(
+        "{GREEN}{BOLD}undone{RESET}{BOLD}:{RESET} transaction {id}, {} of {} changes reverted",
+        status.done, transaction.changes.len()
+    );
+    (status, Some(id))
+}
+
+/// Print what a dry run would have changed.
+pub fn print_plan(changes: &[Change], encoding: Encoding, out: &mut Emitter) {
+    for change in changes {
+        let path = change.path.to_string_lossy();
+        let key = change.key.to_string_lossy();
+        let (key, ktype) = split_key(&key);
+        if !out.human() {
+            let value = |value: &Option<Vec<u8>>| value.as_ref()
+                .map(|value| encoding::display_value(value, encoding).0).into();
+            out.emit(Json::object([
+                ("path", path.as_ref().into()),
+                ("namespace", ktype.name().into()),
+                ("key", key.into()),
+                ("old", value(&change.old)),
+                ("new", value(&change.new)),
+            ]));
+            continue;
         }
+        let value = |value: &Option<Vec<u8>>| match value {
+            Some(value) => format!("\"{}\"", show(value, encoding)),
+            None => format!("{MAGENTA}(none){RESET}"),
+        };
+        outln!(
+            "{}{}{BOLD}{key}{RESET}: {} {YELLOW}->{RESET} {}",
+            prefix(&path, true), namespace(&ktype), value(&change.old), value(&change.new)
+        );
     }
+}
 
-    if !reverse {
-        println!("{GREEN}{BOLD}total{RESET}{BOLD}:{RESET} {present} / {total}");
+pub fn print_index(
+    ctx: &Context, index: &mut Index, index_path: &Path, paths: &[&String], verbose: bool
+) -> Status {
+    let mut status = Status::default();
+    let paths = if paths.is_empty() {
+        index.entries.keys().cloned().collect::<Vec<_>>()
+    } else {
+        paths.iter().map(PathBuf::from).collect()
+    };
+    let mut counts = HashMap::new();
+    for path in paths {
+        match index.refresh(ctx, &path) {
+            Ok(res) => {
+                *counts.entry(res).or_insert(0) += 1;
+                status.add(Outcome::Done);
+                if verbose {
+                    outln!("{BOLD}{GREEN}{}{RESET}{GREEN}:{RESET} {res:?}", path.display());
+                }
+            },
+            Err(err) => {
+                errln!(
+    "{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: could not {YELLOW}index{RED}: {err}.{RESET}",
+                    path.display()
+                );
+                status.add(Outcome::Failed);
+            },
+        }
+    }
+    if let Err(err) = index.save(index_path) {
+        errln!(
+            "{BOLD}{RED}Could not {YELLOW}save{RED} index {DEFAULT}{}{RED}: {err}.{RESET}",
+            index_path.display()
+        );
+        return Status { done: 0, failed: 1, ..Status::default() };
     }
+    let count = |res| counts.get(&res).unwrap_or(&0);
+    outln!(
+"{GREEN}{BOLD}indexed{RESET}{BOLD}:{RESET} {} files, {} added, {} updated, {} unchanged, {} removed",
+        index.entries.len(), count(Refresh::Added), count(Refresh::Updated),
+        count(Refresh::Unchanged), count(Refresh::Removed)
+    );
+    status
 }
@@ -0,0 +1,85 @@
+use std::{
+    env,
+    io::{ self, IsTerminal },
+    sync::atomic::{ AtomicBool, Ordering },
+};
+
+/// Print to stdout, without colour when stdout does not get it.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if !$crate::colour::silenced() {
+            print!("{}", $crate::colour::paint(format!($($arg)*), false))
+        }
+    };
+}
+
+/// Print a line to stdout, without colour when stdout does not get it.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if !$crate::colour::silenced() {
+            println!("{}", $crate::colour::paint(format!($($arg)*), false))
+        }
+    };
+}
+
+/// Print a line to stderr, without colour when stderr does not get it.
+macro_rules! errln {
+    ($($arg:tt)*) => { eprintln!("{}", $crate::colour::paint(format!($($arg)*), true)) };
+}
+
+static OUT: AtomicBool = AtomicBool::new(true);
+static ERR: AtomicBool = AtomicBool::new(true);
+static SILENT: AtomicBool = AtomicBool::new(false);
+
+/// When to colour output.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Colour {
+    /// Colour streams that are terminals, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Decide for stdout and stderr whether they get colour.
+pub fn init(colour: Colour) {
+    let wanted = env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    let (out, err) = match colour {
+        Colour::Always => (true, true),
+        Colour::Never => (false, false),
+        Colour::Auto => (wanted && io::stdout().is_terminal(), wanted && io::stderr().is_terminal()),
+    };
+    OUT.store(out, Ordering::Relaxed);
+    ERR.store(err, Ordering::Relaxed);
+}
+
+/// Stop or start printing messages to stdout with `out` and `outln`.
+pub fn silence(silent: bool) {
+    SILENT.store(silent, Ordering::Relaxed);
+}
+
+pub fn silenced() -> bool {
+    SILENT.load(Ordering::Relaxed)
+}
+
+/// `text` as is when the stream gets colour, otherwise without escape sequences.
+pub fn paint(text: String, stderr: bool) -> String {
+    let colour = if stderr { &ERR } else { &OUT };
+    if colour.load(Ordering::Relaxed) {
+        return text;
+    }
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1B' {
+            res.push(c);
+            continue;
+        }
+        for c in chars.by_ref() {
+            if c.is_ascii_alphabetic() {
+                break;
+            }
+        }
+    }
+    res
+}
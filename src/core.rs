@@ -1,136 +1,599 @@
+use crate::error::ReatError;
+
 use std::{
-    path::Path,
+    path::{ Path, PathBuf },
     ffi::{ OsStr, OsString },
+    cell::RefCell,
 };
 
-pub fn get<P: AsRef<Path>>(path: P, key: &str) -> Option<((String, KeyType), String)> {
-    let mut osstr = OsString::from("user.");
-    osstr.push(key);
-    get_osstr(path, &osstr)
+/// A write to an attribute, made or only planned.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    pub path: PathBuf,
+    /// Fully qualified key.
+    pub key: OsString,
+    /// Whether symlinks were followed, so the write went to the file a link points to.
+    pub follow: bool,
+    /// The value before, `None` if the attribute did not exist.
+    pub old: Option<Vec<u8>>,
+    /// The value after, `None` if the attribute is removed.
+    pub new: Option<Vec<u8>>,
+}
+
+/// How attributes are read and written. Every function that touches files takes one,
+/// so callers with different settings do not affect each other.
+#[derive(Debug)]
+pub struct Context {
+    /// Whether symlinks are followed to the file they point to, which they are by default.
+    /// Without following, the attributes of links themselves are used, like `getfattr -h` does.
+    pub follow: bool,
+    /// Only plan writes instead of making them. Later reads see the planned values,
+    /// and `take_changes` returns the plan.
+    pub dry: bool,
+    /// Record every write, so `take_changes` can return them.
+    pub record: bool,
+    /// The list separator of every key, instead of the one `ListCodec::for_key` finds.
+    pub separator: Option<char>,
+    changes: RefCell<Vec<Change>>,
 }
 
-pub fn get_osstr<P: AsRef<Path>>(path: P, key: &OsStr) -> Option<((String, KeyType), String)> {
-    if let Some(key) = key.to_str() {
-        let val = xattr::get(path, key);
-        if let Ok(Some(val)) = val && let Ok(string) = String::from_utf8(val) {
-            let (key, kt) = split_key(key);
-            return Some(((key.to_string(), kt), string));
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            follow: true,
+            dry: false,
+            record: false,
+            separator: None,
+            changes: RefCell::default(),
         }
     }
-    None
 }
 
-pub fn add_list<P: AsRef<Path>>(path: P, key: &str, value: &str) -> Result<Option<String>, bool> {
-    if let Some((_, old_value)) = get(&path, key) {
-        if old_value.trim() == "" {
-            set(path, key, value, false)
-        } else {
-            set(path, key, &(old_value + "," + value), false)
+impl Context {
+    /// Take the changes recorded or planned so far.
+    pub fn take_changes(&self) -> Vec<Change> {
+        self.changes.take()
+    }
+
+    /// The codec of the list in `key`.
+    pub fn codec(&self, key: &str) -> ListCodec {
+        self.separator.map(ListCodec::new).unwrap_or_else(|| ListCodec::for_key(key))
+    }
+
+    fn plan<P: AsRef<Path>>(&self, path: P, key: &OsStr, new: Option<&[u8]>) {
+        let old = raw_get(self, &path, key).ok();
+        self.push_change(path, key, old, new);
+    }
+
+    fn push_change<P: AsRef<Path>>(
+        &self, path: P, key: &OsStr, old: Option<Vec<u8>>, new: Option<&[u8]>
+    ) {
+        self.changes.borrow_mut().push(Change {
+            path: path.as_ref().to_path_buf(),
+            key: key.to_os_string(),
+            follow: self.follow,
+            old,
+            new: new.map(<[u8]>::to_vec),
+        });
+    }
+}
+
+/// List the fully qualified keys of `path`.
+pub fn list<P: AsRef<Path>>(ctx: &Context, path: P) -> Result<xattr::XAttrs, ReatError> {
+    let list = if ctx.follow { xattr::list_deref(path) } else { xattr::list(path) };
+    Ok(list?)
+}
+
+fn raw_get<P: AsRef<Path>, K: AsRef<OsStr>>(ctx: &Context, path: P, key: K)
+ -> Result<Vec<u8>, ReatError>
+{
+    if ctx.dry {
+        let changes = ctx.changes.borrow();
+        let planned = changes.iter().rev()
+            .find(|change| change.path == path.as_ref() && change.key == key.as_ref());
+        if let Some(change) = planned {
+            return change.new.clone().ok_or(ReatError::NotFound);
         }
+    }
+    let val = if ctx.follow { xattr::get_deref(path, key) } else { xattr::get(path, key) };
+    val?.ok_or(ReatError::NotFound)
+}
+
+fn raw_set<P: AsRef<Path>, K: AsRef<OsStr>>(ctx: &Context, path: P, key: K, value: &[u8])
+ -> Result<(), ReatError>
+{
+    if ctx.dry {
+        ctx.plan(path, key.as_ref(), Some(value));
+        return Ok(());
+    }
+    let old = ctx.record.then(|| raw_get(ctx, &path, &key).ok());
+    let res = if ctx.follow {
+        xattr::set_deref(&path, &key, value)
     } else {
-        set(path, key, value, false)
+        xattr::set(&path, &key, value)
+    };
+    res?;
+    if let Some(old) = old {
+        ctx.push_change(path, key.as_ref(), old, Some(value));
     }
+    Ok(())
 }
 
-// returns Err(true) if failed due to empty requirement.
-// returns Err(false) if failed because of other reasons.
-pub fn set<P: AsRef<Path>>(path: P, key: &str, value: &str, require_empty: bool)
- -> Result<Option<String>, bool>
+fn raw_remove<P: AsRef<Path>, K: AsRef<OsStr>>(ctx: &Context, path: P, key: K)
+ -> Result<(), ReatError>
 {
-    let old_val = if let Some((_, value)) = get(&path, key) { Some(value) } else { None };
-    if require_empty && old_val.is_some() {
-        Err(true)
-    } else if set_raw(path, key, value) {
-        Ok(old_val)
+    if ctx.dry {
+        raw_get(ctx, &path, &key)?;
+        ctx.plan(path, key.as_ref(), None);
+        return Ok(());
+    }
+    let old = ctx.record.then(|| raw_get(ctx, &path, &key).ok());
+    let res = if ctx.follow {
+        xattr::remove_deref(&path, &key)
     } else {
-        Err(false)
+        xattr::remove(&path, &key)
+    };
+    res?;
+    if let Some(old) = old {
+        ctx.push_change(path, key.as_ref(), old, None);
     }
+    Ok(())
 }
 
-pub fn set_raw<P: AsRef<Path>>(path: P, key: &str, value: &str) -> bool {
-    xattr::set(path, "user.".to_string() + key, &Vec::<u8>::from(value)).is_ok()
+/// The fully qualified form of `key`. Keys starting with a namespace, such as `trusted.foo`,
+/// are qualified already, any other key lives in the `user` namespace.
+pub fn qualify(key: &str) -> String {
+    let namespaced = ["user.", "system.", "trusted.", "security."].iter()
+        .any(|namespace| key.starts_with(namespace));
+    if namespaced { key.to_string() } else { "user.".to_string() + key }
 }
 
-pub fn cut_list<P: AsRef<Path>>(path: P, key: &str, value: &str) -> Option<bool> {
-    if let Some((_, old_value)) = get(&path, key) {
-        let mut list = old_value.split(',').collect::<Vec<_>>();
-        let old_len = list.len();
-        list.retain(|item| *item != value);
-        let new_len = list.len();
-        if old_len == new_len {
-            return None;
-        }
-        let mut res = String::new();
-        for item in list {
-            res.push_str(item);
-            res.push(',');
+/// Get the value of `key`, qualified with `qualify`.
+pub fn get<P: AsRef<Path>>(ctx: &Context, path: P, key: &str)
+ -> Result<((String, KeyType), Vec<u8>), ReatError>
+{
+    get_osstr(ctx, path, OsStr::new(&qualify(key)))
+}
+
+/// Get the value of `key` as a string.
+/// Fails with `ReatError::NonUtf8` if the value is binary.
+pub fn get_string<P: AsRef<Path>>(ctx: &Context, path: P, key: &str)
+ -> Result<((String, KeyType), String), ReatError>
+{
+    let (key, val) = get(ctx, path, key)?;
+    let string = String::from_utf8(val).map_err(|_| ReatError::NonUtf8)?;
+    Ok((key, string))
+}
+
+/// Get the value of a fully qualified key, as returned by `list`.
+pub fn get_osstr<P: AsRef<Path>>(ctx: &Context, path: P, key: &OsStr)
+ -> Result<((String, KeyType), Vec<u8>), ReatError>
+{
+    let key = key.to_str().ok_or(ReatError::NonUtf8)?;
+    let val = raw_get(ctx, path, key)?;
+    let (key, kt) = split_key(key);
+    Ok(((key.to_string(), kt), val))
+}
+
+/// Get the value of a fully qualified key without splitting the key.
+pub fn get_raw_osstr<P: AsRef<Path>>(ctx: &Context, path: P, key: &OsStr)
+ -> Result<Vec<u8>, ReatError>
+{
+    raw_get(ctx, path, key)
+}
+
+/// Append `items` to the list in `key`, reading and writing the attribute once.
+pub fn add_list<P: AsRef<Path>, S: AsRef<str>>(
+    ctx: &Context, path: P, key: &str, items: &[S], options: ListOptions
+) -> Result<ListChange, ReatError> {
+    let codec = ctx.codec(key);
+    let old_value = match get_string(ctx, &path, key) {
+        Ok((_, old_value)) => old_value,
+        Err(ReatError::NotFound) => String::new(),
+        Err(err) => return Err(err),
+    };
+    let mut list = codec.decode(&old_value);
+    let mut change = ListChange::default();
+    for item in items {
+        let item = item.as_ref().trim().to_string();
+        if !options.unique || !list.contains(&item) {
+            list.push(item);
+            change.added += 1;
         }
-        res.pop();
-        if set_raw(path, key, &res) {
-            Some(true)
-        } else {
-            Some(false)
+    }
+    if options.sorted && !list.is_sorted() {
+        list.sort();
+        change.reordered = true;
+    }
+    if change.changed() {
+        set_raw(ctx, path, key, codec.encode(&list).as_bytes())?;
+    }
+    Ok(change)
+}
+
+/// Remove duplicate items from the list in `key`, keeping the first occurrence,
+/// and sort the list if asked to.
+pub fn dedup_list<P: AsRef<Path>>(ctx: &Context, path: P, key: &str, sorted: bool)
+ -> Result<ListChange, ReatError>
+{
+    let codec = ctx.codec(key);
+    let (_, old_value) = get_string(ctx, &path, key)?;
+    let old_list = codec.decode(&old_value);
+    let mut list = Vec::with_capacity(old_list.len());
+    for item in &old_list {
+        if !list.contains(item) {
+            list.push(item.clone());
         }
-    } else {
-        None
+    }
+    let mut change = ListChange { removed: old_list.len() - list.len(), ..Default::default() };
+    if sorted && !list.is_sorted() {
+        list.sort();
+        change.reordered = true;
+    }
+    if change.changed() {
+        set_raw(ctx, path, key, codec.encode(&list).as_bytes())?;
+    }
+    Ok(change)
+}
+
+/// How list operations treat duplicates and order.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub struct ListOptions {
+    /// Skip items that are already in the list.
+    pub unique: bool,
+    /// Sort the list after changing it, instead of keeping insertion order.
+    pub sorted: bool,
+}
+
+/// What a list operation changed.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub struct ListChange {
+    pub added: usize,
+    pub removed: usize,
+    /// The list was sorted.
+    pub reordered: bool,
+}
+
+impl ListChange {
+    pub fn changed(&self) -> bool {
+        self.added > 0 || self.removed > 0 || self.reordered
     }
 }
 
-pub fn remove<P: AsRef<Path>>(path: P, key: &str) -> Result<Option<String>, ()> {
-    let old_val = if let Some((_, value)) = get(&path, key) { Some(value) } else { None };
-    if remove_raw(path, key) {
+/// Set `key` to `value` and return the old value, if there was one.
+/// Fails with `ReatError::Protected` if `require_empty` is set and the key already has a value.
+pub fn set<P: AsRef<Path>>(
+    ctx: &Context, path: P, key: &str, value: &[u8], require_empty: bool
+) -> Result<Option<Vec<u8>>, ReatError> {
+    let old_val = match get(ctx, &path, key) {
+        Ok((_, value)) => Some(value),
+        Err(ReatError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+    if require_empty && old_val.is_some() {
+        Err(ReatError::Protected(key.to_string()))
+    } else {
+        set_raw(ctx, path, key, value)?;
         Ok(old_val)
+    }
+}
+
+/// Set `key` to `value` without looking at the old value.
+pub fn set_raw<P: AsRef<Path>>(ctx: &Context, path: P, key: &str, value: &[u8])
+ -> Result<(), ReatError>
+{
+    raw_set(ctx, path, qualify(key), value)
+}
+
+/// Set a fully qualified key to `value` and return the old value, if there was one.
+/// Fails with `ReatError::Protected` if `require_empty` is set and the key already has a value.
+pub fn set_osstr<P: AsRef<Path>>(
+    ctx: &Context, path: P, key: &OsStr, value: &[u8], require_empty: bool
+) -> Result<Option<Vec<u8>>, ReatError> {
+    let old_val = match get_raw_osstr(ctx, &path, key) {
+        Ok(value) => Some(value),
+        Err(ReatError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+    if require_empty && old_val.is_some() {
+        Err(ReatError::Protected(key.to_string_lossy().into_owned()))
     } else {
-        Err(())
+        raw_set(ctx, path, key, value)?;
+        Ok(old_val)
+    }
+}
+
+/// Cut every occurrence of each of `items` from the list in `key`,
+/// reading and writing the attribute once. Returns whether anything was cut.
+pub fn cut_list<P: AsRef<Path>, S: AsRef<str>>(ctx: &Context, path: P, key: &str, items: &[S])
+ -> Result<bool, ReatError>
+{
+    let codec = ctx.codec(key);
+    let (_, old_value) = get_string(ctx, &path, key)?;
+    let mut list = codec.decode(&old_value);
+    let old_len = list.len();
+    list.retain(|item| !items.iter().any(|cut| item == cut.as_ref().trim()));
+    let new_len = list.len();
+    if old_len == new_len {
+        return Ok(false);
     }
+    set_raw(ctx, path, key, codec.encode(&list).as_bytes())?;
+    Ok(true)
 }
 
-pub fn remove_raw<P: AsRef<Path>>(path: P, key: &str) -> bool {
-    xattr::remove(path, "user.".to_string() + key).is_ok()
+/// Remove `key` and return the old value, if there was one.
+pub fn remove<P: AsRef<Path>>(ctx: &Context, path: P, key: &str)
+ -> Result<Option<Vec<u8>>, ReatError>
+{
+    let old_val = if let Ok((_, value)) = get(ctx, &path, key) { Some(value) } else { None };
+    remove_raw(ctx, path, key)?;
+    Ok(old_val)
 }
 
+/// Remove a fully qualified key and return the old value, if there was one.
+pub fn remove_osstr<P: AsRef<Path>>(ctx: &Context, path: P, key: &OsStr)
+ -> Result<Option<Vec<u8>>, ReatError>
+{
+    let old_val = get_raw_osstr(ctx, &path, key).ok();
+    raw_remove(ctx, path, key)?;
+    Ok(old_val)
+}
+
+/// Remove `key` without looking at the old value.
+pub fn remove_raw<P: AsRef<Path>>(ctx: &Context, path: P, key: &str) -> Result<(), ReatError> {
+    raw_remove(ctx, path, qualify(key))
+}
+
+/// Replace every occurrence of `old_value` with `new_value` in the list in `key`.
+/// Returns whether anything was replaced.
 pub fn replace_list<P: AsRef<Path>>(
-    path: P, key: &str, old_value: &str, new_value: &str
-) -> Option<bool> {
-    if let Some((_, old_list)) = get(&path, key) {
-        let mut list = old_list.split(',').collect::<Vec<_>>();
-        if !list.contains(&old_value) {
-            return None;
+    ctx: &Context, path: P, key: &str, old_value: &str, new_value: &str
+) -> Result<bool, ReatError> {
+    let codec = ctx.codec(key);
+    let (_, old_list) = get_string(ctx, &path, key)?;
+    let mut list = codec.decode(&old_list);
+    if !list.iter().any(|item| item == old_value.trim()) {
+        return Ok(false);
+    }
+    for item in &mut list {
+        if item == old_value.trim() {
+            *item = new_value.trim().to_string();
+        }
+    }
+    set_raw(ctx, path, key, codec.encode(&list).as_bytes())?;
+    Ok(true)
+}
+
+/// Splits list attributes into items and joins items back together.
+///
+/// Items are separated by `separator`, a comma unless configured otherwise, and whitespace
+/// around items is trimmed. Items can be quoted with `"` at their start, and `\` escapes
+/// the separator, `"` and `\`, so items can hold the separator, quotes and surrounding
+/// whitespace. Elsewhere quotes and backslashes are part of the item, so plain lists such as
+/// `a,b,c` or `C:\dir,12" vinyl` decode as expected.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub struct ListCodec {
+    pub separator: char,
+}
+
+impl Default for ListCodec {
+    fn default() -> Self {
+        Self { separator: ',' }
+    }
+}
+
+impl ListCodec {
+    pub fn new(separator: char) -> Self {
+        Self { separator }
+    }
+
+    /// The codec for `key`: the separator set for `key` in `$REAT_SEPARATORS`,
+    /// or a comma if there is none.
+    /// `$REAT_SEPARATORS` holds whitespace separated `key=separator` pairs, e.g. `authors=;`.
+    pub fn for_key(key: &str) -> Self {
+        std::env::var("REAT_SEPARATORS").ok()
+            .and_then(|var| var.split_whitespace()
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, sep)| sep.chars().next()))
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    pub fn decode(&self, value: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        if value.trim().is_empty() {
+            return res;
         }
-        for item in &mut list {
-            if *item == old_value {
-                *item = new_value;
+        let mut item = String::new();
+        // Quoted and escaped characters are kept when trimming, up to this length.
+        let mut kept = 0;
+        let mut quoted = false;
+        // Nothing but whitespace has been read of the item yet.
+        let mut fresh = true;
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek().is_some_and(|&c| self.escapable(c)) => {
+                    item.extend(chars.next());
+                    kept = item.len();
+                },
+                '"' if quoted => {
+                    quoted = false;
+                    kept = item.len();
+                },
+                '"' if fresh && self.closes(chars.clone()) => {
+                    quoted = true;
+                },
+                c if c == self.separator && !quoted => {
+                    res.push(trim_item(&item, kept));
+                    item.clear();
+                    kept = 0;
+                    fresh = true;
+                    continue;
+                },
+                c => {
+                    if c.is_whitespace() && !quoted && fresh {
+                        continue;
+                    }
+                    item.push(c);
+                    if quoted {
+                        kept = item.len();
+                    }
+                },
             }
+            fresh = false;
         }
-        let mut res = String::new();
-        for item in list {
-            res.push_str(item);
-            res.push(',');
+        res.push(trim_item(&item, kept));
+        res
+    }
+
+    // Whether `\` escapes `c` rather than being part of the item.
+    fn escapable(&self, c: char) -> bool {
+        c == self.separator || c == '"' || c == '\\'
+    }
+
+    // Whether a quote that was just opened is closed again.
+    fn closes(&self, mut chars: impl Iterator<Item = char>) -> bool {
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return true,
+                '\\' => {
+                    chars.next();
+                },
+                _ => { },
+            }
         }
-        res.pop();
-        if set_raw(path, key, &res) {
-            Some(true)
-        } else {
-            Some(false)
+        false
+    }
+
+    pub fn encode<S: AsRef<str>>(&self, items: &[S]) -> String {
+        let mut res = String::new();
+        for (i, item) in items.iter().enumerate() {
+            let item = item.as_ref();
+            if i > 0 {
+                res.push(self.separator);
+            }
+            let plain = !item.contains(self.separator)
+                && !item.starts_with('"')
+                && !item.ends_with('\\')
+                && !item.contains("\\\\")
+                && !item.contains("\\\"")
+                && item.trim() == item;
+            if plain {
+                res.push_str(item);
+                continue;
+            }
+            res.push('"');
+            for c in item.chars() {
+                if c == '"' || c == '\\' {
+                    res.push('\\');
+                }
+                res.push(c);
+            }
+            res.push('"');
         }
-    } else {
-        None
+        res
     }
 }
 
-fn split_key(key: &str) -> (&str, KeyType) {
-    if key.starts_with("user") { (&key[5..], KeyType::User) }
-    else if key.starts_with("system") { (&key[7..], KeyType::System) }
-    else if key.starts_with("trusted") { (&key[8..], KeyType::Trusted) }
-    else if key.starts_with("security") { (&key[9..], KeyType::Security) }
-    else { (key, KeyType::User) }
+// Trim trailing whitespace that was not quoted or escaped.
+fn trim_item(item: &str, kept: usize) -> String {
+    let (kept, rest) = item.split_at(kept);
+    kept.to_string() + rest.trim_end()
 }
 
-#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+/// Split a fully qualified key into its name and namespace, on the first dot.
+/// A key without a dot has no namespace, which is `KeyType::Other` with an empty name.
+pub fn split_key(key: &str) -> (&str, KeyType) {
+    match key.split_once('.') {
+        Some(("user", name)) => (name, KeyType::User),
+        Some(("system", name)) => (name, KeyType::System),
+        Some(("trusted", name)) => (name, KeyType::Trusted),
+        Some(("security", name)) => (name, KeyType::Security),
+        Some((namespace, name)) => (name, KeyType::Other(namespace.to_string())),
+        None => (key, KeyType::Other(String::new())),
+    }
+}
+
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum KeyType {
     User,
     System,
     Trusted,
     Security,
+    /// Any namespace the others do not cover, as some filesystems and platforms have.
+    Other(String),
+}
+
+impl KeyType {
+    /// The namespace as it appears in fully qualified keys.
+    pub fn name(&self) -> &str {
+        match self {
+            KeyType::User => "user",
+            KeyType::System => "system",
+            KeyType::Trusted => "trusted",
+            KeyType::Security => "security",
+            KeyType::Other(namespace) => namespace,
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(codec: ListCodec, items: &[&str]) {
+        let encoded = codec.encode(items);
+        assert_eq!(codec.decode(&encoded), items, "{encoded}");
+    }
+
+    #[test]
+    fn plain_lists() {
+        let codec = ListCodec::default();
+        assert_eq!(codec.decode("a,b,c"), ["a", "b", "c"]);
+        assert_eq!(codec.decode(" a , b c ,c "), ["a", "b c", "c"]);
+        assert_eq!(codec.decode("a,,b"), ["a", "", "b"]);
+        assert!(codec.decode("  ").is_empty());
+        assert_eq!(codec.encode(&["a", "b", "c"]), "a,b,c");
+    }
+
+    #[test]
+    fn legacy_values_stay_literal() {
+        let codec = ListCodec::default();
+        assert_eq!(codec.decode(r#"C:\dir,12" vinyl"#), [r"C:\dir", r#"12" vinyl"#]);
+        assert_eq!(codec.decode(r#""unclosed,b"#), [r#""unclosed"#, "b"]);
+        assert_eq!(codec.decode(r"a\b,c\"), [r"a\b", r"c\"]);
+        assert_eq!(codec.encode(&[r"C:\dir", r#"12" vinyl"#]), r#"C:\dir,12" vinyl"#);
+        let mut list = codec.decode(r#"C:\dir,12" vinyl"#);
+        list.push("bar".to_string());
+        assert_eq!(codec.decode(&codec.encode(&list)), [r"C:\dir", r#"12" vinyl"#, "bar"]);
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        let codec = ListCodec::default();
+        assert_eq!(codec.decode(r#""a,b", c"#), ["a,b", "c"]);
+        assert_eq!(codec.decode(r#"" a ",b"#), [" a ", "b"]);
+        assert_eq!(codec.decode(r#"a\,b,c"#), ["a,b", "c"]);
+        assert_eq!(codec.decode(r#""a\"b\\",c"#), [r#"a"b\"#, "c"]);
+        assert_eq!(codec.encode(&["a,b", " c"]), r#""a,b"," c""#);
+    }
+
+    #[test]
+    fn round_trips() {
+        let items = ["a", "a,b", " a ", r#""a""#, r#"a"b"#, r"a\b", r"a\", r"\\", r#"\""#, "",
+            r#"a"",b"#, ";", "a;b"];
+        for sep in [',', ';', ' '] {
+            let codec = ListCodec::new(sep);
+            for item in items {
+                // A lone empty item is the same as an empty list.
+                if !item.is_empty() {
+                    round_trip(codec, &[item]);
+                }
+                round_trip(codec, &["x", item, "y"]);
+            }
+            round_trip(codec, &items[1..]);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    core::{ Context, list, get_raw_osstr },
+    encoding::{ escape, unescape, encode_base64, decode_base64, decode_hex },
+    error::ReatError,
+};
+
+use std::{
+    path::Path,
+    ffi::OsString,
+    os::unix::ffi::{ OsStrExt, OsStringExt },
+};
+
+/// First line of a dump in the current format.
+pub const HEADER: &str = "reat dump 2";
+
+/// Format written by `DumpEntry::write`.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum DumpFormat {
+    /// The escaped format that starts with `HEADER`.
+    #[default]
+    Reat,
+    /// The format of `getfattr -d`, as read by `setfattr --restore`.
+    Getfattr,
+}
+
+/// All attributes of one file, with fully qualified keys.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DumpEntry {
+    pub path: OsString,
+    pub attrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl DumpEntry {
+    /// Read all attributes of `path`, sorted by key.
+    pub fn read<P: AsRef<Path>>(ctx: &Context, path: P) -> Result<Self, ReatError> {
+        let mut attrs = Vec::new();
+        for key in list(ctx, &path)? {
+            match get_raw_osstr(ctx, &path, &key) {
+                Ok(val) => attrs.push((key, val)),
+                Err(ReatError::NotFound) => { },
+                Err(err) => return Err(err),
+            }
+        }
+        attrs.sort();
+        Ok(Self { path: path.as_ref().as_os_str().to_os_string(), attrs })
+    }
+
+    /// Write the entry in the given dump format.
+    /// Every path, key and value is escaped, so the entry round-trips byte for byte.
+    pub fn write(&self, format: DumpFormat) -> String {
+        match format {
+            DumpFormat::Reat => {
+                let mut res = format!("f {}\n", escape(self.path.as_bytes()));
+                for (key, val) in &self.attrs {
+                    res.push_str(&format!("a {} {}\n", escape(key.as_bytes()), escape(val)));
+                }
+                res
+            },
+            DumpFormat::Getfattr => {
+                let mut res = format!("# file: {}\n", quote(self.path.as_bytes(), b""));
+                for (key, val) in &self.attrs {
+                    let key = quote(key.as_bytes(), b"=");
+                    match std::str::from_utf8(val) {
+                        Ok(text) if !text.contains('\0') =>
+                            res.push_str(&format!("{key}=\"{}\"\n", quote(val, b"\""))),
+                        _ => res.push_str(&format!("{key}=0s{}\n", encode_base64(val))),
+                    }
+                }
+                res.push('\n');
+                res
+            },
+        }
+    }
+}
+
+/// Parse a dump. Dumps starting with `HEADER` use the current format,
+/// dumps starting with a `# file: ` line are read as `getfattr -d` output,
+/// anything else is read as the original line counting format.
+pub fn parse(dump: &str) -> Result<Vec<DumpEntry>, ReatError> {
+    match dump.split_once('\n') {
+        Some((HEADER, rest)) => parse_v2(rest),
+        None if dump == HEADER => Ok(Vec::new()),
+        _ if dump.trim_start().starts_with("# file: ") => parse_getfattr(dump),
+        _ => parse_v1(dump),
+    }
+}
+
+fn parse_v2(dump: &str) -> Result<Vec<DumpEntry>, ReatError> {
+    let field = |field: &str, nr: usize| unescape(field).ok_or(ReatError::Parse(nr + 2));
+    let mut entries = Vec::new();
+    for (nr, line) in dump.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split(' ').collect::<Vec<_>>();
+        match fields[..] {
+            ["f", path] => entries.push(DumpEntry {
+                path: OsString::from_vec(field(path, nr)?),
+                attrs: Vec::new(),
+            }),
+            ["a", key, val] => {
+                let entry = entries.last_mut().ok_or(ReatError::Parse(nr + 2))?;
+                entry.attrs.push((OsString::from_vec(field(key, nr)?), field(val, nr)?));
+            },
+            _ => return Err(ReatError::Parse(nr + 2)),
+        }
+    }
+    Ok(entries)
+}
+
+// The original format: a path line, a line with the amount of lines of every key and value
+// followed by the amount of dropped attributes, then the keys and values themselves.
+// Only holds `user` attributes, without their prefix.
+fn parse_v1(dump: &str) -> Result<Vec<DumpEntry>, ReatError> {
+    let mut entries = Vec::new();
+    let mut lines = dump.split('\n').enumerate();
+    while let Some((nr, file)) = lines.next() {
+        if file.is_empty() {
+            continue;
+        }
+        let (nr, nums) = lines.next().ok_or(ReatError::Parse(nr + 2))?;
+        if nums == "fail" {
+            continue;
+        }
+        let mut nums = nums.split(' ')
+            .map(|num| num.parse::<usize>().map_err(|_| ReatError::Parse(nr + 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+        nums.pop();
+        if !nums.len().is_multiple_of(2) {
+            return Err(ReatError::Parse(nr + 1));
+        }
+        let mut entry = DumpEntry { path: OsString::from(file), attrs: Vec::new() };
+        let mut take = |n: usize| -> Result<String, ReatError> {
+            let mut res = Vec::new();
+            for _ in 0..n {
+                let (_, line) = lines.next().ok_or(ReatError::Parse(nr + 1))?;
+                res.push(line);
+            }
+            Ok(res.join("\n"))
+        };
+        for pair in nums.chunks(2) {
+            let mut key = OsString::from("user.");
+            key.push(take(pair[0])?);
+            entry.attrs.push((key, take(pair[1])?.into_bytes()));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn parse_getfattr(dump: &str) -> Result<Vec<DumpEntry>, ReatError> {
+    let mut entries = Vec::new();
+    for (nr, line) in dump.lines().enumerate() {
+        let err = || ReatError::Parse(nr + 1);
+        if let Some(path) = line.strip_prefix("# file: ") {
+            entries.push(DumpEntry {
+                path: OsString::from_vec(unquote(path).ok_or_else(err)?),
+                attrs: Vec::new(),
+            });
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, val) = line.split_once('=').unwrap_or((line, ""));
+        let key = OsString::from_vec(unquote(key).ok_or_else(err)?);
+        let val = if let Some(hex) = val.strip_prefix("0x").or(val.strip_prefix("0X")) {
+            decode_hex(hex)
+        } else if let Some(base64) = val.strip_prefix("0s").or(val.strip_prefix("0S")) {
+            decode_base64(base64)
+        } else if let Some(text) = val.strip_prefix('"').and_then(|val| val.strip_suffix('"')) {
+            unquote(text)
+        } else {
+            unquote(val)
+        };
+        let entry = entries.last_mut().ok_or_else(err)?;
+        entry.attrs.push((key, val.ok_or_else(err)?));
+    }
+    Ok(entries)
+}
+
+// Quote the way getfattr does: backslashes, control characters and the given special
+// characters become octal escapes.
+fn quote(value: &[u8], special: &[u8]) -> String {
+    let mut res = String::with_capacity(value.len());
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '\\' {
+                res.push_str("\\\\");
+            } else if c.is_ascii_control() || (c.is_ascii() && special.contains(&(c as u8))) {
+                res.push_str(&format!("\\{:03o}", c as u8));
+            } else {
+                res.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            res.push_str(&format!("\\{byte:03o}"));
+        }
+    }
+    res
+}
+
+// Reverse `quote`, accepting any octal escape.
+fn unquote(value: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(value.len());
+    let bytes = value.as_bytes();
+    let is_octal = |octal: &[u8]| octal.len() == 3 && octal.iter().all(|b| (b'0'..=b'7').contains(b));
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            res.push(bytes[i]);
+            i += 1;
+        } else if let Some(octal) = bytes.get(i + 1..i + 4) && is_octal(octal) {
+            res.push(u8::from_str_radix(std::str::from_utf8(octal).ok()?, 8).ok()?);
+            i += 4;
+        } else {
+            res.push(*bytes.get(i + 1)?);
+            i += 2;
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &[u8], attrs: &[(&[u8], &[u8])]) -> DumpEntry {
+        DumpEntry {
+            path: OsString::from_vec(path.to_vec()),
+            attrs: attrs.iter()
+                .map(|(key, val)| (OsString::from_vec(key.to_vec()), val.to_vec()))
+                .collect(),
+        }
+    }
+
+    fn entries() -> Vec<DumpEntry> {
+        vec![
+            entry(b"/tmp/a file", &[(b"user.tags", b"x,y"), (b"user.empty", b"")]),
+            entry(b"odd\n\xff=\"#", &[
+                (b"user.k=ey \\", b"line\nbreak \"quoted\" \\"),
+                (b"trusted.bin", b"\x00\xff\x01"),
+                (b"user.\xfe", b"# not a comment"),
+            ]),
+            entry(b"bare", &[]),
+        ]
+    }
+
+    #[test]
+    fn reat_round_trips() {
+        let mut dump = format!("{HEADER}\n");
+        for entry in entries() {
+            dump.push_str(&entry.write(DumpFormat::Reat));
+        }
+        assert_eq!(parse(&dump).unwrap(), entries());
+        assert_eq!(parse(HEADER).unwrap(), Vec::new());
+        assert!(matches!(parse(&format!("{HEADER}\na k v\n")), Err(ReatError::Parse(2))));
+        assert!(matches!(parse(&format!("{HEADER}\nf a\nf b c\n")), Err(ReatError::Parse(3))));
+    }
+
+    #[test]
+    fn getfattr_round_trips() {
+        let dump = entries().iter()
+            .map(|entry| entry.write(DumpFormat::Getfattr))
+            .collect::<String>();
+        assert_eq!(parse(&dump).unwrap(), entries());
+        let dump = "# file: a\nuser.x=0x6869\nuser.y=0SaGk=\nuser.z=\"h\\151\"\nuser.w\n";
+        let hi = b"hi".as_slice();
+        assert_eq!(parse(dump).unwrap(), vec![
+            entry(b"a", &[(b"user.x", hi), (b"user.y", hi), (b"user.z", hi), (b"user.w", b"")]),
+        ]);
+    }
+
+    #[test]
+    fn original_format() {
+        let dump = "a\n1 2 0\ntags\nx,\ny\nb\nfail\nc\n0\n";
+        assert_eq!(parse(dump).unwrap(), vec![
+            entry(b"a", &[(b"user.tags", b"x,\ny")]),
+            entry(b"c", &[]),
+        ]);
+        assert!(matches!(parse("a\n1 2\n"), Err(ReatError::Parse(2))));
+        assert!(parse("a\n1 5 0\ntags\nx\n").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How values that are not valid UTF-8 are shown.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// Show `value` as text if it is valid UTF-8, otherwise encode it.
+/// Returns the string and whether it was encoded.
+pub fn display_value(value: &[u8], encoding: Encoding) -> (String, bool) {
+    match std::str::from_utf8(value) {
+        Ok(string) => (string.to_string(), false),
+        Err(_) => (encode(value, encoding), true),
+    }
+}
+
+/// Encode `value` with a `0x` (hex) or `0s` (base64) marker, like `getfattr` does.
+pub fn encode(value: &[u8], encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Hex => "0x".to_string() + &encode_hex(value),
+        Encoding::Base64 => "0s".to_string() + &encode_base64(value),
+    }
+}
+
+pub fn encode_hex(value: &[u8]) -> String {
+    let mut res = String::with_capacity(value.len() * 2);
+    for byte in value {
+        res.push_str(&format!("{byte:02x}"));
+    }
+    res
+}
+
+pub fn encode_base64(value: &[u8]) -> String {
+    let mut res = String::with_capacity(value.len().div_ceil(3) * 4);
+    for chunk in value.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+/// Escape `value` into valid UTF-8 without whitespace, so it can be used as a field in a line.
+pub fn escape(value: &[u8]) -> String {
+    let mut res = String::with_capacity(value.len());
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => res.push_str("\\\\"),
+                ' ' => res.push_str("\\s"),
+                '\n' => res.push_str("\\n"),
+                '\t' => res.push_str("\\t"),
+                '\r' => res.push_str("\\r"),
+                c if c.is_control() || c.is_whitespace() => {
+                    for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                        res.push_str(&format!("\\x{byte:02x}"));
+                    }
+                },
+                c => res.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            res.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    res
+}
+
+/// Reverse `escape`. Returns `None` if `value` contains an invalid escape sequence.
+pub fn unescape(value: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            res.push(byte);
+            continue;
+        }
+        match bytes.next()? {
+            b'\\' => res.push(b'\\'),
+            b's' => res.push(b' '),
+            b'n' => res.push(b'\n'),
+            b't' => res.push(b'\t'),
+            b'r' => res.push(b'\r'),
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                res.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            },
+            _ => return None,
+        }
+    }
+    Some(res)
+}
+
+/// Decode hex, without the `0x` marker. Returns `None` if `value` is not valid hex.
+pub fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decode base64, without the `0s` marker. Returns `None` if `value` is not valid base64.
+pub fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim_end_matches('=');
+    let mut res = Vec::with_capacity(value.len() / 4 * 3);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in value.bytes() {
+        n = (n << 6) | BASE64.iter().position(|b| *b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((n >> bits) as u8);
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trips() {
+        let values: [&[u8]; 6] = [b"", b"plain", b"a b\tc\nd\re", b"back\\slash \\s",
+            b"\xff\x00\x7f", "caf\u{e9} \u{a0}\u{2028}".as_bytes()];
+        for value in values {
+            let escaped = escape(value);
+            assert!(!escaped.contains(char::is_whitespace), "{escaped}");
+            assert_eq!(unescape(&escaped).as_deref(), Some(value));
+        }
+        assert_eq!(escape(b"a b\\"), "a\\sb\\\\");
+        assert_eq!(unescape("\\q"), None);
+        assert_eq!(unescape("\\x4"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode_base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode_base64(b"foob"), "Zm9vYg==");
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for len in 0..bytes.len() {
+            let value = &bytes[len..];
+            assert_eq!(decode_base64(&encode_base64(value)).as_deref(), Some(value));
+        }
+        assert_eq!(decode_base64("Zm9v!"), None);
+        assert_eq!(decode_hex(&encode_hex(&bytes)), Some(bytes));
+        assert_eq!(decode_hex("abc"), None);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    fmt,
+    io::{ self, ErrorKind },
+};
+
+/// Everything that can go wrong when working with extended attributes.
+#[derive(Debug)]
+pub enum ReatError {
+    /// The attribute is not present on the file.
+    NotFound,
+    /// The key is protected and can not be changed without force.
+    Protected(String),
+    /// The caller lacks the privileges to access the attribute.
+    PermissionDenied(io::Error),
+    /// The filesystem or platform does not support extended attributes.
+    Unsupported(io::Error),
+    /// The key or value is too large to be stored.
+    TooLarge(io::Error),
+    /// The key or value is not valid UTF-8.
+    NonUtf8,
+    /// A dump could not be parsed, at the given line.
+    Parse(usize),
+    /// A query could not be parsed.
+    InvalidQuery(String),
+    /// A glob or regular expression could not be compiled.
+    InvalidPattern(String),
+    /// Any other io error.
+    Io(io::Error),
+}
+
+impl From<io::Error> for ReatError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::PermissionDenied => ReatError::PermissionDenied(err),
+            ErrorKind::Unsupported => ReatError::Unsupported(err),
+            ErrorKind::ArgumentListTooLong | ErrorKind::FileTooLarge | ErrorKind::StorageFull
+                => ReatError::TooLarge(err),
+            _ => ReatError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for ReatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReatError::NotFound => write!(f, "attribute not found"),
+            ReatError::Protected(key) => write!(f, "attribute {key} is protected"),
+            ReatError::PermissionDenied(err) => write!(f, "permission denied: {err}"),
+            ReatError::Unsupported(err) => write!(f, "not supported: {err}"),
+            ReatError::TooLarge(err) => write!(f, "too large: {err}"),
+            ReatError::NonUtf8 => write!(f, "not valid utf-8"),
+            ReatError::Parse(line) => write!(f, "could not parse line {line}"),
+            ReatError::InvalidQuery(msg) => write!(f, "invalid query: {msg}"),
+            ReatError::InvalidPattern(msg) => write!(f, "invalid pattern: {msg}"),
+            ReatError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ReatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReatError::PermissionDenied(err) | ReatError::Unsupported(err)
+                | ReatError::TooLarge(err) | ReatError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
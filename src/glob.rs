@@ -0,0 +1,117 @@
+/// A shell style wildcard pattern: `*` matches any sequence, `?` any single character,
+/// `[abc]`, `[a-z]` and `[!a-z]` a character class, and `\` escapes the next character.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Any,
+    One,
+    Class(Vec<(char, char)>, bool),
+    Lit(char),
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Any | Token::One => true,
+            Token::Class(ranges, negated) =>
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated,
+            Token::Lit(lit) => *lit == c,
+        }
+    }
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => if tokens.last() != Some(&Token::Any) { tokens.push(Token::Any) },
+                '?' => tokens.push(Token::One),
+                '\\' => tokens.push(Token::Lit(chars.next().unwrap_or('\\'))),
+                '[' => {
+                    let rest = chars.clone().collect::<Vec<_>>();
+                    let Some(end) = rest.iter().skip(1).position(|c| *c == ']') else {
+                        tokens.push(Token::Lit('['));
+                        continue;
+                    };
+                    let end = end + 1;
+                    let class = rest[..end].to_vec();
+                    let negated = matches!(class.first(), Some('!' | '^'));
+                    let class = if negated { &class[1..] } else { &class[..] };
+                    let mut ranges = Vec::new();
+                    let mut i = 0;
+                    while i < class.len() {
+                        if i + 2 < class.len() && class[i + 1] == '-' {
+                            ranges.push((class[i], class[i + 2]));
+                            i += 3;
+                        } else {
+                            ranges.push((class[i], class[i]));
+                            i += 1;
+                        }
+                    }
+                    tokens.push(Token::Class(ranges, negated));
+                    for _ in 0..=end {
+                        chars.next();
+                    }
+                },
+                c => tokens.push(Token::Lit(c)),
+            }
+        }
+        Self { tokens }
+    }
+
+    /// Whether the whole of `text` matches the pattern.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let p = &self.tokens;
+        let (mut pi, mut ti) = (0, 0);
+        let mut star = None;
+        while ti < text.len() {
+            if pi < p.len() && p[pi] == Token::Any {
+                star = Some((pi, ti));
+                pi += 1;
+            } else if pi < p.len() && p[pi].matches(text[ti]) {
+                pi += 1;
+                ti += 1;
+            } else if let Some((spi, sti)) = star {
+                pi = spi + 1;
+                ti = sti + 1;
+                star = Some((spi, sti + 1));
+            } else {
+                return false;
+            }
+        }
+        p[pi..].iter().all(|token| *token == Token::Any)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let glob = Glob::new("*.jp*g");
+        assert!(glob.matches("a.jpg") && glob.matches(".jpeg") && glob.matches("a.b.jpg"));
+        assert!(!glob.matches("a.jpgx") && !glob.matches("jpg"));
+        assert!(Glob::new("").matches("") && !Glob::new("").matches("a"));
+        assert!(Glob::new("**").matches("") && Glob::new("a*b*c").matches("abbbc"));
+        assert!(Glob::new("?\u{e9}?").matches("x\u{e9}y") && !Glob::new("??").matches("x"));
+    }
+
+    #[test]
+    fn classes_and_escapes() {
+        let glob = Glob::new("[a-c]x[!0-9]");
+        assert!(glob.matches("bxy") && !glob.matches("dxy") && !glob.matches("bx5"));
+        assert!(Glob::new("[^a]").matches("b") && !Glob::new("[^a]").matches("a"));
+        assert!(Glob::new("[]]").matches("]"));
+        assert!(Glob::new("[ab").matches("[ab") && !Glob::new("[ab").matches("a"));
+        assert!(Glob::new("\\*").matches("*") && !Glob::new("\\*").matches("x"));
+        assert!(Glob::new("a\\").matches("a\\"));
+    }
+}
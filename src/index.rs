@@ -0,0 +1,232 @@
+use crate::{
+    core::{ Context, qualify },
+    dump::DumpEntry,
+    encoding::{ escape, unescape },
+    error::ReatError,
+};
+
+use std::{
+    env, fs,
+    path::{ Path, PathBuf },
+    ffi::OsString,
+    collections::BTreeMap,
+    os::unix::{ fs::MetadataExt, ffi::{ OsStrExt, OsStringExt } },
+};
+
+/// First line of an index file.
+pub const HEADER: &str = "reat index 1";
+
+/// The `user` attributes of a file, together with what is needed to tell whether they are stale.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IndexEntry {
+    pub ino: u64,
+    /// Modification time in nanoseconds.
+    pub mtime: i64,
+    /// Status change time in nanoseconds, changing an attribute updates it.
+    pub ctime: i64,
+    /// Fully qualified keys and their values.
+    pub attrs: Vec<(OsString, Vec<u8>)>,
+}
+
+/// What `Index::refresh` did with a path.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub enum Refresh {
+    Added,
+    Updated,
+    Unchanged,
+    Removed,
+}
+
+/// An on-disk index of `user` attributes, keyed by absolute path.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Index {
+    pub entries: BTreeMap<PathBuf, IndexEntry>,
+}
+
+impl Index {
+    /// The index file in `$REAT_INDEX`, or else in the user cache directory.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("REAT_INDEX") {
+            return Some(PathBuf::from(path));
+        }
+        let cache = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache.join("reat").join("index"))
+    }
+
+    /// Load the index at `path`. A missing index file is an empty index.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReatError> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut lines = data.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(ReatError::Parse(1));
+        }
+        let mut index = Self::default();
+        let mut last = None;
+        for (nr, line) in lines {
+            let err = || ReatError::Parse(nr + 1);
+            let fields = line.split(' ').collect::<Vec<_>>();
+            match fields[..] {
+                ["f", path, ino, mtime, ctime] => {
+                    let path = PathBuf::from(OsString::from_vec(unescape(path).ok_or_else(err)?));
+                    let entry = IndexEntry {
+                        ino: ino.parse().map_err(|_| err())?,
+                        mtime: mtime.parse().map_err(|_| err())?,
+                        ctime: ctime.parse().map_err(|_| err())?,
+                        attrs: Vec::new(),
+                    };
+                    index.entries.insert(path.clone(), entry);
+                    last = Some(path);
+                },
+                ["a", key, val] => {
+                    let entry = last.as_ref().and_then(|path| index.entries.get_mut(path))
+                        .ok_or_else(err)?;
+                    let key = OsString::from_vec(unescape(key).ok_or_else(err)?);
+                    entry.attrs.push((key, unescape(val).ok_or_else(err)?));
+                },
+                [""] => { },
+                _ => return Err(err()),
+            }
+        }
+        Ok(index)
+    }
+
+    /// Write the index to `path`, creating its directory if needed.
+    /// The index is written to a temporary file first, so a crash can not leave it half written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReatError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut data = String::from(HEADER);
+        data.push('\n');
+        for (path, entry) in &self.entries {
+            data.push_str(&format!(
+                "f {} {} {} {}\n",
+                escape(path.as_os_str().as_bytes()), entry.ino, entry.mtime, entry.ctime
+            ));
+            for (key, val) in &entry.attrs {
+                data.push_str(&format!("a {} {}\n", escape(key.as_bytes()), escape(val)));
+            }
+        }
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Bring the entry of `path` up to date. Attributes are only read again when the inode,
+    /// modification time or status change time differ from the indexed ones.
+    pub fn refresh<P: AsRef<Path>>(&mut self, ctx: &Context, path: P)
+     -> Result<Refresh, ReatError>
+    {
+        let path = std::path::absolute(path)?;
+        let (ino, mtime, ctime) = match stamp(ctx, &path) {
+            Ok(stamp) => stamp,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(match self.entries.remove(&path) {
+                    Some(_) => Refresh::Removed,
+                    None => Refresh::Unchanged,
+                });
+            },
+            Err(err) => return Err(err.into()),
+        };
+        let old = self.entries.get(&path);
+        if let Some(old) = old && old.stamp() == (ino, mtime, ctime) {
+            return Ok(Refresh::Unchanged);
+        }
+        let res = if old.is_some() { Refresh::Updated } else { Refresh::Added };
+        let mut attrs = DumpEntry::read(ctx, &path)?.attrs;
+        attrs.retain(|(key, _)| key.as_bytes().starts_with(b"user."));
+        self.entries.insert(path, IndexEntry { ino, mtime, ctime, attrs });
+        Ok(res)
+    }
+
+    /// Whether the index stores `key`. Only `user` attributes are indexed, other keys have to be
+    /// read from the file.
+    pub fn covers(key: &str) -> bool {
+        qualify(key).starts_with("user.")
+    }
+
+    /// The indexed entry of `path`, if there is one and the file has not changed since it was
+    /// indexed. Stale entries have to be read from the file instead.
+    pub fn entry<P: AsRef<Path>>(&self, ctx: &Context, path: P) -> Option<&IndexEntry> {
+        let path = std::path::absolute(path).ok()?;
+        let entry = self.entries.get(&path)?;
+        (stamp(ctx, &path).ok()? == entry.stamp()).then_some(entry)
+    }
+}
+
+// The inode, modification time and status change time of `path`, or of the link itself when
+// symlinks are not followed.
+fn stamp(ctx: &Context, path: &Path) -> std::io::Result<(u64, i64, i64)> {
+    let meta = if ctx.follow { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+    let mtime = meta.mtime() * 1_000_000_000 + meta.mtime_nsec();
+    let ctime = meta.ctime() * 1_000_000_000 + meta.ctime_nsec();
+    Ok((meta.ino(), mtime, ctime))
+}
+
+impl IndexEntry {
+    fn stamp(&self) -> (u64, i64, i64) {
+        (self.ino, self.mtime, self.ctime)
+    }
+
+    /// The indexed value of `key`, qualified like everywhere else.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        let key = qualify(key);
+        self.attrs.iter()
+            .find(|(k, _)| k.as_bytes() == key.as_bytes())
+            .map(|(_, val)| &val[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualified_keys() {
+        let entry = IndexEntry {
+            attrs: vec![("user.tags".into(), b"a,b".to_vec())],
+            ..Default::default()
+        };
+        assert_eq!(entry.get("tags"), Some(&b"a,b"[..]));
+        assert_eq!(entry.get("user.tags"), Some(&b"a,b"[..]));
+        assert_eq!(entry.get("trusted.tags"), None);
+        assert!(Index::covers("tags") && Index::covers("user.tags"));
+        assert!(!Index::covers("trusted.tags") && !Index::covers("security.tags"));
+    }
+    #[test]
+    fn stale_entries_are_read_again() {
+        let dir = env::temp_dir().join(format!("reat-index-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (file, link) = (dir.join("file"), dir.join("link"));
+        fs::write(&file, "").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        xattr::set(&file, "user.tags", b"a").unwrap();
+        let ctx = Context::default();
+        let mut nofollow = Context::default();
+        nofollow.follow = false;
+        let mut index = Index::default();
+        assert_eq!(index.refresh(&ctx, &file).unwrap(), Refresh::Added);
+        assert_eq!(index.entry(&ctx, &file).and_then(|entry| entry.get("tags")), Some(&b"a"[..]));
+        xattr::set(&file, "user.tags", b"b").unwrap();
+        let stale = index.entry(&ctx, &file).is_none();
+        let updated = index.refresh(&ctx, &file).unwrap();
+        // Without following, the link itself is indexed, which holds no user attributes.
+        index.refresh(&nofollow, &link).unwrap();
+        let link_attrs = index.entry(&nofollow, &link).map(|entry| entry.attrs.len());
+        let followed = index.entry(&ctx, &link).is_none();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(stale);
+        assert_eq!(updated, Refresh::Updated);
+        assert_eq!(link_attrs, Some(0));
+        assert!(followed);
+    }
+}
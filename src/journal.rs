@@ -0,0 +1,229 @@
+use crate::{
+    core::Change,
+    encoding::{ escape, unescape },
+    error::ReatError,
+};
+
+use std::{
+    env, fs,
+    io::{ Read, Seek, SeekFrom, Write },
+    path::{ Path, PathBuf },
+    ffi::OsString,
+    time::{ SystemTime, UNIX_EPOCH },
+    os::unix::ffi::{ OsStrExt, OsStringExt },
+};
+
+/// First line of a journal file.
+pub const HEADER: &str = "reat journal 1";
+
+/// The changes made by one command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transaction {
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    /// The transaction this one undid, if it was an undo.
+    pub undoes: Option<u64>,
+    /// The command line that made the changes.
+    pub command: Vec<String>,
+    /// The changes in the order they were made, with absolute paths.
+    pub changes: Vec<Change>,
+}
+
+/// An append-only record of every change, to undo them with.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Journal {
+    pub transactions: Vec<Transaction>,
+}
+
+impl Journal {
+    /// The journal file in `$REAT_JOURNAL`, or else in the user state directory.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("REAT_JOURNAL") {
+            return Some(PathBuf::from(path));
+        }
+        let state = env::var_os("XDG_STATE_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+        Some(state.join("reat").join("journal"))
+    }
+
+    /// Load the journal at `path`. A missing journal file is an empty journal.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReatError> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut lines = data.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(ReatError::Parse(1));
+        }
+        let mut journal = Self::default();
+        for (nr, line) in lines {
+            let err = || ReatError::Parse(nr + 1);
+            let bytes = |field: &str| unescape(field).ok_or_else(err);
+            let value = |field: &str| -> Result<_, ReatError> {
+                match field.strip_prefix('=') {
+                    Some(field) => Ok(Some(bytes(field)?)),
+                    None if field == "-" => Ok(None),
+                    None => Err(err()),
+                }
+            };
+            let fields = line.split(' ').collect::<Vec<_>>();
+            match fields[..] {
+                ["t", id, time, undoes, ref command @ ..] => {
+                    let command = command.iter()
+                        .map(|arg| String::from_utf8(bytes(arg)?).map_err(|_| err()))
+                        .collect::<Result<_, _>>()?;
+                    journal.transactions.push(Transaction {
+                        id: id.parse().map_err(|_| err())?,
+                        time: time.parse().map_err(|_| err())?,
+                        undoes: match undoes {
+                            "-" => None,
+                            id => Some(id.parse().map_err(|_| err())?),
+                        },
+                        command,
+                        changes: Vec::new(),
+                    });
+                },
+                ["c", path, key, old, new, ref mode @ ..] => {
+                    let transaction = journal.transactions.last_mut().ok_or_else(err)?;
+                    transaction.changes.push(Change {
+                        path: PathBuf::from(OsString::from_vec(bytes(path)?)),
+                        key: OsString::from_vec(bytes(key)?),
+                        follow: match mode {
+                            [] => true,
+                            ["h"] => false,
+                            _ => return Err(err()),
+                        },
+                        old: value(old)?,
+                        new: value(new)?,
+                    });
+                },
+                [""] => { },
+                _ => return Err(err()),
+            }
+        }
+        Ok(journal)
+    }
+
+    /// Append the changes made by `command` to the journal at `path` as a new transaction,
+    /// creating the journal if needed. Returns the id of the transaction.
+    /// The journal is locked while appending, so concurrent runs get their own ids.
+    pub fn append<P: AsRef<Path>>(
+        path: P, undoes: Option<u64>, command: &[String], changes: &[Change]
+    ) -> Result<u64, ReatError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        file.lock()?;
+        let len = file.metadata()?.len();
+        let last = match last_id(&mut file, len)? {
+            Some(id) => id,
+            None if len == 0 => 0,
+            None => Self::load(path)?.transactions.last().map(|transaction| transaction.id)
+                .unwrap_or(0),
+        };
+        let id = last + 1;
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs())
+            .unwrap_or(0);
+        let value = |value: &Option<Vec<u8>>| match value {
+            Some(value) => format!("={}", escape(value)),
+            None => "-".to_string(),
+        };
+        let mut data = String::new();
+        if len == 0 {
+            data.push_str(HEADER);
+            data.push('\n');
+        }
+        data.push_str(&format!("t {id} {time} "));
+        data.push_str(&undoes.map(|id| id.to_string()).unwrap_or("-".to_string()));
+        for arg in command {
+            data.push(' ');
+            data.push_str(&escape(arg.as_bytes()));
+        }
+        data.push('\n');
+        for change in changes {
+            let change_path = std::path::absolute(&change.path)?;
+            data.push_str(&format!(
+                "c {} {} {} {}{}\n",
+                escape(change_path.as_os_str().as_bytes()), escape(change.key.as_bytes()),
+                value(&change.old), value(&change.new), if change.follow { "" } else { " h" }
+            ));
+        }
+        file.write_all(data.as_bytes())?;
+        Ok(id)
+    }
+
+    /// The transaction with `id`.
+    pub fn get(&self, id: u64) -> Option<&Transaction> {
+        self.transactions.iter().find(|transaction| transaction.id == id)
+    }
+
+    /// Whether a later transaction undid `id`.
+    pub fn undone(&self, id: u64) -> bool {
+        self.transactions.iter().any(|transaction| transaction.undoes == Some(id))
+    }
+
+    /// The latest transaction that is not an undo and was not undone.
+    pub fn last(&self) -> Option<&Transaction> {
+        self.transactions.iter().rev()
+            .find(|transaction| transaction.undoes.is_none() && !self.undone(transaction.id))
+    }
+}
+
+// The id of the last transaction in the first `len` bytes of `file`, read from the end so
+// appending does not slow down as the journal grows. Gives nothing if there is no
+// transaction or the last one does not parse, `Journal::load` can tell which.
+fn last_id(file: &mut fs::File, len: u64) -> Result<Option<u64>, ReatError> {
+    let mut tail = Vec::new();
+    let mut start = len;
+    while start > 0 {
+        let from = start.saturating_sub(1 << 12);
+        let mut chunk = vec![0; (start - from) as usize];
+        file.seek(SeekFrom::Start(from))?;
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut tail);
+        tail = chunk;
+        start = from;
+        let line = tail.windows(3).rposition(|window| window == b"\nt ");
+        if let Some(pos) = line {
+            let id = tail[pos + 3..].split(|byte| *byte == b' ').next()
+                .and_then(|id| std::str::from_utf8(id).ok())
+                .and_then(|id| id.parse().ok());
+            return Ok(id);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_and_load() {
+        let path = env::temp_dir().join(format!("reat-journal-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let change = |follow| Change {
+            path: PathBuf::from("/tmp/a b"),
+            key: "user.k".into(),
+            follow,
+            old: None,
+            new: Some(b"v\n".to_vec()),
+        };
+        let command = ["reat".to_string(), "set".to_string()];
+        assert_eq!(Journal::append(&path, None, &command, &[change(true)]).unwrap(), 1);
+        assert_eq!(Journal::append(&path, Some(1), &command, &[change(false)]).unwrap(), 2);
+        let journal = Journal::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(journal.transactions.len(), 2);
+        assert_eq!(journal.transactions[0].changes, [change(true)]);
+        assert_eq!(journal.transactions[1].changes, [change(false)]);
+        assert_eq!(journal.transactions[1].undoes, Some(1));
+        assert!(journal.undone(1));
+        assert_eq!(journal.last(), None);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// A JSON value, for writing machine readable output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Build an object from key value pairs, keeping their order.
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Self {
+        Json::Object(fields.into_iter().map(|(key, val)| (key.to_string(), val)).collect())
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Self {
+        Json::String(string.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Self {
+        Json::String(string)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as i64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(option: Option<T>) -> Self {
+        option.map(Into::into).unwrap_or(Json::Null)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(vec: Vec<T>) -> Self {
+        Json::Array(vec.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(string) => write_string(f, string),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, val)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{val}")?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Ergonomic use of extended attributes of filesystem objects.
+//!
+//! Keys passed to the functions in this crate live in the `user` namespace: `tags` refers to the
+//! extended attribute `user.tags`. Keys that start with a namespace, such as `trusted.foo` or
+//! `security.selinux`, are used as they are.
+//!
+//! Every function that touches files takes a `Context`, which says whether symlinks are followed
+//! and whether writes are made, only planned or also recorded.
+
+pub mod core;
+pub mod dump;
+pub mod encoding;
+pub mod error;
+pub mod glob;
+pub mod index;
+pub mod journal;
+pub mod json;
+pub mod matcher;
+pub mod mirror;
+pub mod protect;
+pub mod query;
+pub mod sort;
+pub mod walk;
+
+pub use crate::{
+    core::*,
+    encoding::Encoding,
+    error::ReatError,
+};
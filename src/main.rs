@@ -1,31 +1,93 @@
-mod core;
+#[macro_use]
+mod colour;
 mod actions;
 
 use actions::*;
+use colour::Colour;
+use reat::{
+    Context,
+    Encoding,
+    ListOptions,
+    dump::DumpFormat,
+    glob::Glob,
+    walk::{ Walker, Links },
+    index::Index,
+    journal::Journal,
+    protect::Protections,
+    query::Query,
+    matcher::{ Matcher, MatchMode },
+    mirror::Counterpart,
+    sort::{ Order, Missing, SortOptions },
+};
 
 use std::{
     env,
+    io::Read,
+    path::PathBuf,
     process::ExitCode,
 };
 
 use zen_colour::*;
 
 fn main() -> ExitCode {
+    colour::init(Colour::Auto);
     if !xattr::SUPPORTED_PLATFORM {
-        println!("{BOLD}{RED}This platform does not support {DEFAULT}xattr{RED}.{RESET}");
-        return ExitCode::FAILURE;
+        errln!("{BOLD}{RED}This platform does not support {DEFAULT}xattr{RED}.{RESET}");
+        return ExitCode::from(EXIT_FAILURE);
     }
 
     let mut verbose = false;
     let mut force = false;
     let mut stdin = false;
+    let mut null = false;
+    let mut dry = false;
     let mut into_a = false;
+    let mut encoding = None;
+    let mut format = None;
+    let mut output = None;
+    let mut recursive = false;
+    let mut cached = false;
+    let mut matching = None;
+    let mut nocase = false;
+    let mut separator = None;
+    let mut list_options = ListOptions::default();
+    let mut multi = false;
+    let mut multi_first = false;
+    let mut index_path = None;
+    let mut journal_path = None;
+    let mut copy_options = CopyOptions::default();
+    let mut walker = Walker::default();
+    let mut links = None;
+    let mut merge = None;
+    let mut by = None;
+    let mut order = None;
+    let mut missing = None;
+    let mut descending = false;
+    let mut nofollow = false;
     let mut mode = " ";
     let mut a = Vec::new();
     let mut b = Vec::new();
 
     for arg in env::args().skip(1) {
-        if (arg == "verbose" || arg == "v") && !verbose {
+        if arg == "-" {
+            into_a = false;
+        }
+        else if mode == " " && let Some(word) = mode_word(&arg) {
+            mode = word;
+            into_a = true;
+        }
+        // Multi can also come directly after set or add.
+        else if (arg == "multi" || arg == "m") && matches!(mode, "s" | "a") && a.is_empty()
+            && !multi
+        {
+            multi = true;
+        }
+        // Options only come before the mode and the pairs of multi, so after them every word is
+        // an attribute, value or path, even when it looks like an option.
+        else if mode != " " || multi {
+            if into_a { a.push(arg) } else { b.push(arg) }
+        }
+        else if (arg == "verbose" || arg == "v") && !verbose {
             verbose = true;
         }
         else if (arg == "force" || arg == "f") && !force {
@@ -34,75 +96,179 @@ fn main() -> ExitCode {
         else if (arg == "stdin" || arg == "i") && !stdin {
             stdin = true;
         }
-        else if arg == "-" {
-            into_a = false;
+        else if (arg == "null" || arg == "0") && !null {
+            null = true;
         }
-        else if (arg == "list" || arg == "l") && mode == " " {
-            mode = "l";
-            into_a = true;
+        else if (arg == "dry" || arg == "n") && !dry {
+            dry = true;
         }
-        else if (arg == "get" || arg == "g") && mode == " " {
-            mode = "g";
-            into_a = true;
+        else if arg == "hex" && encoding.is_none() {
+            encoding = Some(Encoding::Hex);
         }
-        else if (arg == "set" || arg == "s") && mode == " " {
-            mode = "s";
-            into_a = true;
+        else if (arg == "base64" || arg == "b64") && encoding.is_none() {
+            encoding = Some(Encoding::Base64);
         }
-        else if (arg == "rem" || arg == "r") && mode == " " {
-            mode = "r";
-            into_a = true;
+        else if let Some(name) = arg.strip_prefix("format=") && format.is_none() {
+            format = match name {
+                "reat" => Some(DumpFormat::Reat),
+                "getfattr" => Some(DumpFormat::Getfattr),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}format{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
         }
-        else if (arg == "add" || arg == "a") && mode == " " {
-            mode = "a";
-            into_a = true;
+        else if arg == "json" && output.is_none() {
+            output = Some(Output::Json);
         }
-        else if (arg == "cut" || arg == "c") && mode == " " {
-            mode = "c";
-            into_a = true;
+        else if arg == "ndjson" && output.is_none() {
+            output = Some(Output::Ndjson);
         }
-        else if (arg == "clear" || arg == "cl") && mode == " " {
-            mode = "cl";
-            into_a = true;
+        else if let Some(name) = arg.strip_prefix("colour=").or(arg.strip_prefix("color=")) {
+            match name {
+                "auto" => colour::init(Colour::Auto),
+                "always" => colour::init(Colour::Always),
+                "never" => colour::init(Colour::Never),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}colour{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            }
         }
-        else if (arg == "copy" || arg == "cp") && mode == " " {
-            mode = "cp";
-            into_a = true;
+        else if (arg == "recursive" || arg == "rec") && !recursive {
+            recursive = true;
         }
-        else if (arg == "contains" || arg == "cn") && mode == " " {
-            mode = "cn";
-            into_a = true;
+        else if arg == "hidden" && !walker.hidden {
+            walker.hidden = true;
         }
-        else if (arg == "contains-all" || arg == "cna") && mode == " " {
-            mode = "cna";
-            into_a = true;
+        else if arg == "follow" && links.is_none() {
+            links = Some(Links::All);
         }
-        else if (arg == "contains-not" || arg == "cnn") && mode == " " {
-            mode = "cnn";
-            into_a = true;
+        else if let Some(name) = arg.strip_prefix("links=") && links.is_none() {
+            links = match name {
+                "roots" => Some(Links::Roots),
+                "all" => Some(Links::All),
+                "never" => Some(Links::Never),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}links{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
         }
-        else if (arg == "rename" || arg == "rn") && mode == " " {
-            mode = "rn";
-            into_a = true;
+        else if (arg == "nofollow" || arg == "h") && !nofollow {
+            nofollow = true;
         }
-        else if (arg == "replace" || arg == "rp") && mode == " " {
-            mode = "rp";
-            into_a = true;
+        else if let Some(depth) = arg.strip_prefix("depth=") && walker.max_depth.is_none() {
+            let Ok(depth) = depth.parse() else {
+                errln!("{BOLD}{RED}Invalid {YELLOW}depth{RED} {DEFAULT}{depth}{RED}.{RESET}");
+                return ExitCode::from(EXIT_USAGE);
+            };
+            walker.max_depth = Some(depth);
         }
-        else if (arg == "dump" || arg == "d") && mode == " " {
-            mode = "d";
-            into_a = true;
+        else if let Some(glob) = arg.strip_prefix("include=") {
+            walker.include.push(Glob::new(glob));
         }
-        else if (arg == "restore" || arg == "rs") && mode == " " {
-            mode = "rs";
-            into_a = true;
+        else if let Some(glob) = arg.strip_prefix("exclude=") {
+            walker.exclude.push(Glob::new(glob));
         }
-        else if (arg == "rank" || arg == "ra") && mode == " " {
-            mode = "ra";
-            into_a = true;
+        else if let Some(glob) = arg.strip_prefix("keys=") {
+            copy_options.keys.push(Glob::new(glob));
+        }
+        else if let Some(glob) = arg.strip_prefix("nokeys=") {
+            copy_options.nokeys.push(Glob::new(glob));
+        }
+        else if let Some(name) = arg.strip_prefix("merge=") && merge.is_none() {
+            merge = match name {
+                "overwrite" => Some(Merge::Overwrite),
+                "keep" => Some(Merge::Keep),
+                "union" => Some(Merge::Union),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}merge{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
+        }
+        else if let Some(name) = arg.strip_prefix("by=") && by.is_none() {
+            by = match name {
+                "path" => Some(Counterpart::Path),
+                "stem" => Some(Counterpart::Stem),
+                "hash" => Some(Counterpart::Hash),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}by{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
+        }
+        else if let Some(name) = arg.strip_prefix("order=") && order.is_none() {
+            order = match name {
+                "lex" => Some(Order::Lexicographic),
+                "natural" => Some(Order::Natural),
+                "date" => Some(Order::Date),
+                "version" => Some(Order::Version),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}order{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
+        }
+        else if let Some(name) = arg.strip_prefix("missing=") && missing.is_none() {
+            missing = match name {
+                "first" => Some(Missing::First),
+                "last" => Some(Missing::Last),
+                "drop" => Some(Missing::Drop),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}missing{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
+        }
+        else if arg == "desc" && !descending {
+            descending = true;
+        }
+        else if let Some(name) = arg.strip_prefix("match=") && matching.is_none() {
+            matching = match name {
+                "exact" => Some(MatchMode::Exact),
+                "prefix" => Some(MatchMode::Prefix),
+                "substring" => Some(MatchMode::Substring),
+                "glob" => Some(MatchMode::Glob),
+                "regex" => Some(MatchMode::Regex),
+                _ => {
+                    errln!("{BOLD}{RED}Unknown {YELLOW}match{RED} {DEFAULT}{name}{RED}.{RESET}");
+                    return ExitCode::from(EXIT_USAGE);
+                },
+            };
+        }
+        else if arg == "nocase" && !nocase {
+            nocase = true;
+        }
+        else if let Some(sep) = arg.strip_prefix("sep=") && separator.is_none() {
+            let mut chars = sep.chars();
+            let (Some(sep), None) = (chars.next(), chars.next()) else {
+                errln!(
+                    "{BOLD}{RED}A {YELLOW}separator{RED} has to be a single character.{RESET}"
+                );
+                return ExitCode::from(EXIT_USAGE);
+            };
+            separator = Some(sep);
+        }
+        else if (arg == "multi" || arg == "m") && !multi {
+            multi = true;
+            multi_first = true;
         }
-        else if into_a {
-            a.push(arg);
+        else if arg == "unique" && !list_options.unique {
+            list_options.unique = true;
+        }
+        else if arg == "sorted" && !list_options.sorted {
+            list_options.sorted = true;
+        }
+        else if arg == "cached" && !cached {
+            cached = true;
+        }
+        else if let Some(path) = arg.strip_prefix("index=") && index_path.is_none() {
+            index_path = Some(PathBuf::from(path));
+        }
+        else if let Some(path) = arg.strip_prefix("journal=") && journal_path.is_none() {
+            journal_path = Some(PathBuf::from(path));
         }
         else {
             b.push(arg);
@@ -112,15 +278,83 @@ fn main() -> ExitCode {
     if mode == " " {
         mode = "l";
     }
+    let encoding = encoding.unwrap_or_default();
+    copy_options.merge = merge.unwrap_or_default();
+    copy_options.user = mode == "sy" || !force;
+    walker.links = links.unwrap_or(if nofollow { Links::Never } else { Links::Roots });
+    let mut ctx = Context::default();
+    ctx.follow = !nofollow;
+    ctx.separator = separator;
+    let mutating = matches!(
+        mode, "s" | "r" | "a" | "c" | "cl" | "cp" | "rn" | "rp" | "rs" | "dd" | "u" | "sy"
+    );
+    let dry = dry && mutating;
+    if dry {
+        ctx.dry = true;
+        colour::silence(true);
+    }
+    let journal_path = journal_path.or_else(Journal::default_path);
+    ctx.record = mutating && !dry && journal_path.is_some();
+    let protect = if force || !mutating {
+        Protections::default()
+    } else {
+        match Protections::load() {
+            Ok(protect) => protect,
+            Err(err) => {
+                errln!(
+                    "{BOLD}{RED}Could not {YELLOW}load{RED} protected attributes: {err}.{RESET}"
+                );
+                return ExitCode::from(EXIT_FAILURE);
+            },
+        }
+    };
+    let format = format.unwrap_or_default();
 
+    let mut index = None;
+    if mode == "ix" || cached {
+        let Some(path) = index_path.or_else(Index::default_path) else {
+            errln!("{BOLD}{RED}No {YELLOW}index{RED} path, set {YELLOW}index=path{RED}.{RESET}");
+            return ExitCode::from(EXIT_USAGE);
+        };
+        match Index::load(&path) {
+            Ok(loaded) => index = Some((loaded, path)),
+            Err(err) => {
+                errln!(
+            "{BOLD}{RED}Could not {YELLOW}load{RED} index {DEFAULT}{}{RED}: {err}.{RESET}",
+                    path.display()
+                );
+                return ExitCode::from(EXIT_FAILURE);
+            },
+        }
+    }
+
+    let mut status = Status::default();
     let mut stdin_refs = Vec::new();
+    let mut walked = Vec::new();
+    let indexed: Vec<String>;
     let mut ps = Vec::new();
     let mut nps = Vec::new();
     let mut dump = String::new();
 
     if stdin {
-        let stdin = std::io::read_to_string(std::io::stdin());
-        if let Ok(input) = stdin {
+        let mut input = Vec::new();
+        if std::io::stdin().read_to_end(&mut input).is_err() {
+            input.clear();
+        }
+        if null {
+            for file in input.split(|byte| *byte == 0) {
+                match String::from_utf8(file.to_vec()) {
+                    Ok(file) => stdin_refs.push(file),
+                    Err(file) => {
+                        errln!(
+        "{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: skipped, path is not valid {YELLOW}UTF-8{RED}.{RESET}",
+                            String::from_utf8_lossy(file.as_bytes())
+                        );
+                        status.add(Outcome::Failed);
+                    },
+                }
+            }
+        } else if let Ok(input) = String::from_utf8(input) {
             for file in input.split('\n') {
                 stdin_refs.push(file.trim().to_string());
             }
@@ -135,13 +369,48 @@ fn main() -> ExitCode {
         if let Ok(input) = stdin {
             dump = input;
         } else {
-            println!("{BOLD}{RED} restore data could not be read into stdin!");
-            return ExitCode::FAILURE;
+            errln!("{BOLD}{RED}Restore data could not be read from stdin!{RESET}");
+            return ExitCode::from(EXIT_FAILURE);
         }
     }
 
+    let records = matches!(mode, "l" | "g" | "d" | "ra" | "cn" | "cna" | "cnn" | "q" | "so");
+    if output.is_some() && !records && !dry {
+        errln!(
+"{BOLD}{RED}Can only use {YELLOW}json{RED} or {YELLOW}ndjson{RED} with modes that print results, or with {YELLOW}dry{RED}.{RESET}"
+        );
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if multi && mode != "s" && mode != "a" {
+        errln!(
+"{BOLD}{RED}Can only use {YELLOW}multi{RED} with {YELLOW}set{RED} or {YELLOW}add{RED}.{RESET}"
+        );
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    // Without -, only the first word is the query, so more query words would be taken as paths.
+    if mode == "q" && !stdin && b.is_empty() && a.iter().skip(1).any(|word| query_operator(word)) {
+        errln!(
+"{BOLD}{RED}A {YELLOW}query{RED} of several words has to be followed by {YELLOW}-{RED} and the paths.{RESET}"
+        );
+        return ExitCode::from(EXIT_USAGE);
+    }
+
     match (mode, &a[..], &b[..]) {
-        ("l" | "cp" | "d" | "rs" | "cl", apaths, bpaths) => {
+        (_, pairs, paths) if multi && !multi_first => {
+            nps.extend(pairs);
+            ps.extend(paths);
+        },
+        (_, paths, pairs) if multi => {
+            nps.extend(pairs);
+            ps.extend(paths);
+        },
+        // The source comes before paths read from stdin, which are destinations.
+        ("cp", apaths, bpaths) => {
+            ps.splice(0..0, apaths.iter().chain(bpaths));
+        },
+        ("l" | "d" | "rs" | "cl" | "ix" | "sy", apaths, bpaths) => {
             for path in apaths {
                 ps.push(path);
             }
@@ -149,12 +418,19 @@ fn main() -> ExitCode {
                 ps.push(path);
             }
         },
-        ("g" | "r" | "ra", [att, paths @ ..], []) => {
+        // With paths from stdin, every word is part of the query.
+        ("g" | "r" | "ra" | "q" | "dd" | "so", [att, paths @ ..], []) if mode != "q" || !stdin => {
             nps.push(att);
             for path in paths {
                 ps.push(path);
             }
         },
+        ("rp", [att, old_val, new_val, paths @ ..], []) => {
+            nps.extend([att, old_val, new_val]);
+            for path in paths {
+                ps.push(path);
+            }
+        },
         ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn" | "rp", [att, val, paths @ ..], []) => {
             nps.push(att);
             nps.push(val);
@@ -172,120 +448,326 @@ fn main() -> ExitCode {
         },
     }
 
-    let no_path = || println!("{BOLD}{RED}No {YELLOW}path{RED} provided!{RESET}");
+    // Sync walks both trees itself.
+    if recursive && mode != "sy" {
+        if mode == "cp" || mode == "rs" {
+            errln!(
+"{BOLD}{RED}Can not use {YELLOW}recursive{RED} with {YELLOW}copy{RED} or {YELLOW}restore{RED}.{RESET}"
+            );
+            return ExitCode::from(EXIT_USAGE);
+        }
+        for path in &ps {
+            for res in walker.walk(path) {
+                match res {
+                    Ok(path) => match path.into_os_string().into_string() {
+                        Ok(path) => walked.push(path),
+                        Err(path) => {
+                            errln!(
+"{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: skipped, path is not valid {YELLOW}UTF-8{RED}.{RESET}",
+                                path.to_string_lossy()
+                            );
+                            status.add(Outcome::Failed);
+                        },
+                    },
+                    Err((path, err)) => {
+                        errln!(
+"{BOLD}{GREEN}{}{RESET}{RED}{BOLD}: could not {YELLOW}walk{RED} directory: {err}.{RESET}",
+                            path.display()
+                        );
+                        status.add(Outcome::Failed);
+                    },
+                }
+            }
+        }
+        ps = walked.iter().collect();
+    }
+
+    if cached && ps.is_empty() && let Some((index, _)) = &index {
+        indexed = index.entries.keys().filter_map(|path| path.to_str()).map(String::from)
+            .collect();
+        ps = indexed.iter().collect();
+    }
+    let cache = if cached { index.as_ref().map(|(index, _)| index) } else { None };
+
+    let mut out = Emitter::new(output.unwrap_or_default(), null);
+
+    let no_path = format!("{BOLD}{RED}No {YELLOW}path{RED} provided!{RESET}");
+    let mut undone = None;
 
     match (mode, &nps[..], &ps[..]) {
-        ("l" | "d" | "cp" | "rs", _, []) => no_path(),
-        ("l", _, paths) => for path in paths {
-            print_list(path, paths.len() > 1, verbose);
+        ("l" | "d" | "cp" | "sy", _, []) => status.usage(&no_path),
+        ("s" | "a", [], _) if multi => status.usage(format_args!(
+"{BOLD}{RED}No {YELLOW}attributes{RED} nor {YELLOW}values{RED} provided!{RESET}"
+        )),
+        ("s" | "a", pairs, _) if multi && pairs.len() % 2 == 1 => status.usage(format_args!(
+"{BOLD}{RED}Missing a {YELLOW}value{RED} for attribute {DEFAULT}{}{RED}!{RESET}",
+            pairs[pairs.len() - 1]
+        )),
+        ("s" | "a", _, []) if multi => status.usage(&no_path),
+        ("s", pairs, paths) if multi => for path in paths { for pair in pairs.chunks(2) {
+            status.add(print_set(&ctx, path, pair[0], pair[1], paths.len() > 1, &protect));
+        }},
+        ("a", pairs, paths) if multi => for path in paths { for pair in pairs.chunks(2) {
+            let (attr, value) = (pair[0], pair[1]);
+            let outcome = print_add_list(
+                &ctx, path, attr, value, paths.len() > 1, list_options, &protect
+            );
+            status.add(outcome);
+        }},
+        ("u", ids, paths) if ids.len() + paths.len() > 1 => status.usage(format_args!(
+            "{BOLD}{RED}Can only {YELLOW}undo{RED} one transaction at a time.{RESET}"
+        )),
+        ("u", ids, paths) => {
+            let id = ids.iter().chain(paths).next().map(|id| (id, id.parse::<u64>()));
+            match (id, &journal_path) {
+                (Some((id, Err(_))), _) => status.usage(format_args!(
+                    "{BOLD}{RED}Invalid {YELLOW}transaction{RED} {DEFAULT}{id}{RED}.{RESET}"
+                )),
+                (_, None) => status.usage(format_args!(
+                "{BOLD}{RED}No {YELLOW}journal{RED} path, set {YELLOW}journal=path{RED}.{RESET}"
+                )),
+                (id, Some(path)) => match Journal::load(path) {
+                    Ok(journal) => {
+                        let id = id.and_then(|(_, id)| id.ok());
+                        let res;
+                        (res, undone) = print_undo(&mut ctx, &journal, id, verbose, force, &protect);
+                        status.merge(res);
+                    },
+                    Err(err) => {
+                        errln!(
+            "{BOLD}{RED}Could not {YELLOW}load{RED} journal {DEFAULT}{}{RED}: {err}.{RESET}",
+                            path.display()
+                        );
+                        status.add(Outcome::Failed);
+                    },
+                },
+            }
+        },
+        ("ix", _, paths) => if let Some((index, path)) = &mut index {
+            status.merge(print_index(&ctx, index, path, paths, verbose));
         },
-        ("d", _, paths) => for path in paths {
-            print_dump(path);
+        ("l", _, paths) => for path in paths {
+            status.add(print_list(&ctx, path, paths.len() > 1, verbose, encoding, &mut out));
         },
+        ("d", _, paths) => status.merge(print_dump(&ctx, paths, format, encoding, &mut out)),
         ("rs", _, paths) => {
-            print_restore(&dump, paths, verbose, force);
+            status.merge(print_restore(&ctx, &dump, paths, verbose, force, &protect));
         },
         ("cl", _, paths) => for path in paths {
-            print_clear(path, paths.len() > 1, verbose, force);
+            status.add(print_clear(&ctx, path, paths.len() > 1, verbose, &protect));
         },
-        ("cp", _, [_]) => println!("{BOLD}{RED}Need at least 2 {YELLOW}paths{RED}.{RESET}"),
-        ("cp", _, [srcp, dstp]) => print_copy(srcp, dstp),
-        ("cp", _, _) => println!("{BOLD}{RED}To many {YELLOW}paths{RED}.{RESET}"),
-        ("g" | "r", [], []) => println!(
+        ("cp", _, [_]) => status.usage(format_args!(
+            "{BOLD}{RED}Need at least 2 {YELLOW}paths{RED}.{RESET}"
+        )),
+        ("cp", _, [srcp, dstps @ ..]) => for dstp in dstps {
+            let outcome = print_copy(&ctx, srcp, dstp, dstps.len() > 1, &copy_options, &protect);
+            status.add(outcome);
+        },
+        ("sy", _, [_]) => status.usage(format_args!(
+            "{BOLD}{RED}Need a source and a destination {YELLOW}path{RED}.{RESET}"
+        )),
+        ("sy", _, [src, dst]) => {
+            let by = by.unwrap_or_default();
+            status.merge(print_sync(&ctx, src, dst, &walker, by, &copy_options, &protect));
+        },
+        ("sy", _, _) => status.usage(format_args!(
+            "{BOLD}{RED}To many {YELLOW}paths{RED}.{RESET}"
+        )),
+        ("so", [], _) => status.usage(format_args!(
+            "{BOLD}{RED}No {YELLOW}attribute{RED} provided!{RESET}"
+        )),
+        ("so", [_], []) => status.usage(&no_path),
+        ("so", [attr], paths) => {
+            let options = SortOptions {
+                order: order.unwrap_or_default(),
+                descending,
+                missing: missing.unwrap_or_default(),
+            };
+            status.merge(print_sort(&ctx, attr, paths, cache, options, &mut out));
+        },
+        ("so", _, _) => status.usage(format_args!(
+            "{BOLD}{RED}Can only {YELLOW}sort{RED} by one attribute.{RESET}"
+        )),
+        ("g" | "r" | "dd", [], []) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}path{RED} nor {YELLOW}attribute{RED} provided!{RESET}"
-        ),
-        ("g" | "r" | "ra", [], [_]) => println!(
+        )),
+        ("g" | "r" | "ra" | "dd", [], [_]) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}attribute{RED} provided!{RESET}"
-        ),
-        ("g" | "r" | "ra", [_], []) => no_path(),
+        )),
+        ("g" | "r" | "ra" | "dd", [_], []) => status.usage(&no_path),
         ("g", attrs, paths) => for path in paths { for attr in attrs {
-            print_get(path, attr, paths.len() > 1, verbose);
+            status.add(print_get(&ctx, path, attr, paths.len() > 1, verbose, encoding, &mut out));
         }},
-        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn", [], []) => println!(
+        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn", [], []) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}path{RED} nor {YELLOW}attribute{RED} nor {YELLOW}value{RED} provided!{RESET}"
-        ),
-        ("rp", [], []) => println!(
+        )),
+        ("rp", [], []) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}path{RED} nor {YELLOW}attribute{RED} nor {YELLOW}values{RED} provided!{RESET}"
-        ),
-        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn", [], [_]) => println!(
+        )),
+        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn", [], [_]) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}attribute{RED} nor {YELLOW}value{RED} provided!{RESET}"
-        ),
-        ("rp", [], [_]) => println!(
+        )),
+        ("rp", [], [_]) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}attribute{RED} nor {YELLOW}values{RED} provided!{RESET}"
-        ),
-        ("rp", [_], [_]) => println!(
+        )),
+        ("rp", [_], _) => status.usage(format_args!(
 "{BOLD}{RED}Missing an {YELLOW}attribute{RED} and a {YELLOW}value{RED} or two {YELLOW}values{RED}!{RESET}"
-        ),
-        ("rp", [_, _], [_]) => println!(
+        )),
+        ("rp", [_, _], _) => status.usage(format_args!(
 "{BOLD}{RED}Missing an {YELLOW}attribute{RED} or a {YELLOW}value{RED}!{RESET}"
-        ),
-        ("s" | "a" | "c", [_], []) => println!(
+        )),
+        ("s" | "a" | "c" | "rn", [_], []) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}path{RED} provided and missing {YELLOW}attribute{RED} or {YELLOW}value{RED}!{RESET}"
-        ),
-        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn" | "rp", [_, _], []) => no_path(),
-        ("s" | "a" | "c", [_], [_]) => println!(
+        )),
+        ("s" | "a" | "c" | "cn" | "cna" | "cnn" | "rn", [_, _], []) =>
+            status.usage(&no_path),
+        ("s" | "a" | "c" | "rn", [_], _) => status.usage(format_args!(
 "{BOLD}{RED}No {YELLOW}attribute{RED} or {YELLOW}value{RED} provided!{RESET}"
-        ),
+        )),
+        ("s" | "a" | "c" | "rn" | "rp", _, []) => status.usage(&no_path),
         ("s", [attrs @ .., value], paths) => for path in paths { for attr in attrs {
-            print_set(path, attr, value, paths.len() > 1, force);
+            status.add(print_set(&ctx, path, attr, value, paths.len() > 1, &protect));
         }},
         ("r", attrs, paths) => for path in paths { for attr in attrs {
-            print_remove(path, attr, paths.len() > 1, force);
+            status.add(print_remove(&ctx, path, attr, paths.len() > 1, &protect));
+        }},
+        ("dd", attrs, paths) => for path in paths { for attr in attrs {
+            let sorted = list_options.sorted;
+            let outcome = print_dedup(
+                &ctx, path, attr, paths.len() > 1, verbose, sorted, &protect
+            );
+            status.add(outcome);
         }},
         ("a", [attrs @ .., value], paths) => for path in paths { for attr in attrs {
-            print_add_list(path, attr, value, paths.len() > 1);
+            let outcome = print_add_list(
+                &ctx, path, attr, value, paths.len() > 1, list_options, &protect
+            );
+            status.add(outcome);
         }},
         ("c", [attrs @ .., value], paths) => for path in paths { for attr in attrs {
-            print_cut_list(path, attr, value, paths.len() > 1, verbose);
+            let outcome = print_cut_list(
+                &ctx, path, attr, value, paths.len() > 1, verbose, &protect
+            );
+            status.add(outcome);
         }},
-        ("cn", [_], []) => no_path(),
-        ("cn", [attr], paths) => for path in paths {
-            print_contains('o', attr, &[], path);
-        },
-        ("cn", [attr, values @ ..], paths) => for path in paths {
-            print_contains('o', attr, values, path);
-        },
-        ("cna", [attr], paths) => for path in paths {
-            print_contains('a', attr, &[], path);
-        },
-        ("cna", [attr, values @ ..], paths) => for path in paths {
-            print_contains('a', attr, values, path);
-        },
-        ("cnn", [attr], paths) => for path in paths {
-            print_contains('n', attr, &[], path);
+        ("cn" | "cna" | "cnn", [_], []) => status.usage(&no_path),
+        ("cn" | "cna" | "cnn", [attr, values @ ..], paths) => {
+            let matching = matching.unwrap_or(MatchMode::default_for(attr));
+            let matchers = values.iter()
+                .map(|value| Matcher::new(value, matching, nocase))
+                .collect::<Result<Vec<_>, _>>();
+            let cmode = match mode { "cn" => 'o', "cna" => 'a', _ => 'n' };
+            match matchers {
+                Ok(matchers) => for path in paths {
+                    let outcome = print_contains(
+                        &ctx, cmode, attr, &matchers, path, cache, &mut out
+                    );
+                    status.add(outcome);
+                },
+                Err(err) => status.usage(format_args!("{BOLD}{RED}{err}.{RESET}")),
+            }
         },
-        ("cnn", [attr, values @ ..], paths) => for path in paths {
-            print_contains('n', attr, values, path);
+        ("q", [], _) => status.usage(format_args!(
+            "{BOLD}{RED}No {YELLOW}query{RED} provided!{RESET}"
+        )),
+        ("q", _, []) => status.usage(&no_path),
+        ("q", words, paths) => {
+            let words = words.iter().map(|word| word.as_str()).collect::<Vec<_>>();
+            match Query::parse(&words.join(" "), matching, nocase) {
+                Ok(query) => for path in paths {
+                    status.add(print_query(&ctx, &query, path, cache, &mut out));
+                },
+                Err(err) => status.usage(format_args!("{BOLD}{RED}{err}.{RESET}")),
+            }
         },
         ("rn", [attrs @ .., value], paths) => for path in paths { for attr in attrs {
-            print_rename(path, attr, value, paths.len() > 1, force);
+            status.add(print_rename(&ctx, path, attr, value, paths.len() > 1, force, &protect));
         }},
         ("rp", [attrs @ .., old_val, new_val], paths) => for path in paths { for attr in attrs {
-            print_replace(path, attr, old_val, new_val, paths.len() > 1, verbose);
+            let replacement = Replacement { old: old_val, new: new_val };
+            let outcome = print_replace(
+                &ctx, path, attr, replacement, paths.len() > 1, verbose, &protect
+            );
+            status.add(outcome);
         }},
         ("ra", [], paths) => {
-            print_rank("", paths, "", "");
+            status.merge(print_rank(&ctx, "", paths, "", "", cache, &mut out));
         },
         ("ra", [fa], paths) if *fa == "flip" || *fa == "reverse" => {
-            print_rank("", paths, fa, "");
+            status.merge(print_rank(&ctx, "", paths, fa, "", cache, &mut out));
         },
         ("ra", [fa, fb], paths)
             if (*fa == "flip" || *fa == "reverse") && (*fb == "flip" || *fb == "reverse") =>
         {
-            print_rank("", paths, fa, fb);
+            status.merge(print_rank(&ctx, "", paths, fa, fb, cache, &mut out));
         },
         ("ra", [attr], paths) => {
-            print_rank(attr, paths, "", "");
+            status.merge(print_rank(&ctx, attr, paths, "", "", cache, &mut out));
         },
         ("ra", [attr, flag], paths) => {
-            print_rank(attr, paths, flag, "");
+            status.merge(print_rank(&ctx, attr, paths, flag, "", cache, &mut out));
         },
         ("ra", [attr, flag_a, flag_b], paths) => {
-            print_rank(attr, paths, flag_a, flag_b);
+            status.merge(print_rank(&ctx, attr, paths, flag_a, flag_b, cache, &mut out));
         },
-        _ => { },
+        ("ra", _, _) => status.usage(format_args!(
+"{BOLD}{RED}Can only {YELLOW}rank{RED} by one attribute with {YELLOW}flip{RED} and {YELLOW}reverse{RED}.{RESET}"
+        )),
+        _ => status.usage(format_args!(
+            "{BOLD}{RED}Could not make sense of the {YELLOW}arguments{RED}.{RESET}"
+        )),
+    }
+    if dry {
+        colour::silence(false);
+        print_plan(&ctx.take_changes(), encoding, &mut out);
     }
+    let changes = ctx.take_changes();
+    if !changes.is_empty() && let Some(path) = &journal_path {
+        let command = env::args().collect::<Vec<_>>();
+        // The changes are made already, so a journal that can not be written only warns.
+        if let Err(err) = Journal::append(path, undone, &command, &changes) {
+            errln!(
+"{BOLD}{YELLOW}Warning{RED}: could not {YELLOW}write{RED} journal {DEFAULT}{}{RED}, these changes can not be undone: {err}.{RESET}",
+                path.display()
+            );
+        }
+    }
+    out.finish();
 
-    ExitCode::SUCCESS
+    ExitCode::from(status.code())
 }
 
+// The mode a word selects, in its long and short forms.
+fn mode_word(arg: &str) -> Option<&'static str> {
+    match arg {
+        "list" | "l" => Some("l"),
+        "get" | "g" => Some("g"),
+        "set" | "s" => Some("s"),
+        "rem" | "r" => Some("r"),
+        "add" | "a" => Some("a"),
+        "cut" | "c" => Some("c"),
+        "clear" | "cl" => Some("cl"),
+        "copy" | "cp" => Some("cp"),
+        "contains" | "cn" => Some("cn"),
+        "contains-all" | "cna" => Some("cna"),
+        "contains-not" | "cnn" => Some("cnn"),
+        "rename" | "rn" => Some("rn"),
+        "replace" | "rp" => Some("rp"),
+        "dump" | "d" => Some("d"),
+        "restore" | "rs" => Some("rs"),
+        "rank" | "ra" => Some("ra"),
+        "query" | "q" => Some("q"),
+        "dedup" | "dd" => Some("dd"),
+        "index" | "ix" => Some("ix"),
+        "sync" | "mirror" => Some("sy"),
+        "sort" => Some("so"),
+        "undo" => Some("u"),
+        _ => None,
+    }
+}
+
+// Whether `word` is an operator or opens or closes a group, so it can only be part of a query.
+fn query_operator(word: &str) -> bool {
+    matches!(word, "and" | "or" | "not" | "&" | "|")
+        || word.starts_with(['(', '!']) || word.ends_with(')')
+}
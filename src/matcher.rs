@@ -0,0 +1,78 @@
+use crate::{
+    error::ReatError,
+    glob::Glob,
+};
+
+use regex::{ Regex, RegexBuilder };
+
+/// How list items are compared to a value.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub enum MatchMode {
+    /// The item equals the value.
+    Exact,
+    /// The item starts with the value.
+    Prefix,
+    /// The item contains the value.
+    Substring,
+    /// The item matches the value as a shell style wildcard pattern.
+    Glob,
+    /// The item contains a match of the value as a regular expression.
+    Regex,
+}
+
+impl MatchMode {
+    /// The mode used when none is given: exact for `tags`, substring for everything else.
+    pub fn default_for(key: &str) -> Self {
+        if key == "tags" { MatchMode::Exact } else { MatchMode::Substring }
+    }
+}
+
+/// A value compiled for matching list items against.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    kind: Kind,
+    nocase: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Exact(String),
+    Prefix(String),
+    Substring(String),
+    Glob(Glob),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn new(value: &str, mode: MatchMode, nocase: bool) -> Result<Self, ReatError> {
+        let folded = if nocase { value.to_lowercase() } else { value.to_string() };
+        let kind = match mode {
+            MatchMode::Exact => Kind::Exact(folded),
+            MatchMode::Prefix => Kind::Prefix(folded),
+            MatchMode::Substring => Kind::Substring(folded),
+            MatchMode::Glob => Kind::Glob(Glob::new(&folded)),
+            MatchMode::Regex => Kind::Regex(
+                RegexBuilder::new(value).case_insensitive(nocase).build()
+                    .map_err(|err| ReatError::InvalidPattern(err.to_string()))?
+            ),
+        };
+        Ok(Self { kind, nocase })
+    }
+
+    pub fn matches(&self, item: &str) -> bool {
+        let folded;
+        let item = if self.nocase && !matches!(self.kind, Kind::Regex(_)) {
+            folded = item.to_lowercase();
+            &folded
+        } else {
+            item
+        };
+        match &self.kind {
+            Kind::Exact(value) => item == value,
+            Kind::Prefix(value) => item.starts_with(value.as_str()),
+            Kind::Substring(value) => item.contains(value.as_str()),
+            Kind::Glob(glob) => glob.matches(item),
+            Kind::Regex(regex) => regex.is_match(item),
+        }
+    }
+}
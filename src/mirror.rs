@@ -0,0 +1,111 @@
+use crate::{
+    error::ReatError,
+    walk::Walker,
+};
+
+use std::{
+    fs,
+    io::{ self, Read },
+    path::{ Path, PathBuf },
+    ffi::OsString,
+    hash::{ DefaultHasher, Hasher },
+    collections::BTreeMap,
+};
+
+/// How files in one tree are matched with their counterparts in another.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Counterpart {
+    /// The same path relative to the root.
+    #[default]
+    Path,
+    /// The same path relative to the root, without the extension, to match re-encoded files.
+    Stem,
+    /// The same content. Only regular files are matched.
+    Hash,
+}
+
+/// The files of two trees, matched up.
+#[derive(Debug, Default)]
+pub struct Pairing {
+    /// Source files and their counterparts in the destination.
+    pub pairs: Vec<(PathBuf, PathBuf)>,
+    /// Source files without a counterpart in the destination.
+    pub missing_dst: Vec<PathBuf>,
+    /// Destination files without a counterpart in the source.
+    pub missing_src: Vec<PathBuf>,
+    /// Files that match more than one file, as the source files and the destination files.
+    pub ambiguous: Vec<(Vec<PathBuf>, Vec<PathBuf>)>,
+    /// Files and directories that could not be walked or read.
+    pub errors: Vec<(PathBuf, ReatError)>,
+}
+
+/// Walk `src` and `dst` with `walker` and match the files in them up by `by`.
+/// Everything is returned in sorted order.
+pub fn pair<P: AsRef<Path>>(walker: &Walker, src: P, dst: P, by: Counterpart) -> Pairing {
+    let mut res = Pairing::default();
+    let mut files = BTreeMap::<OsString, (Vec<PathBuf>, Vec<PathBuf>)>::new();
+    for (root, is_src) in [(src.as_ref(), true), (dst.as_ref(), false)] {
+        for path in walker.walk(root) {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => {
+                    res.errors.push(err);
+                    continue;
+                },
+            };
+            let key = match counterpart_key(root, &path, by) {
+                Ok(Some(key)) => key,
+                Ok(None) => continue,
+                Err(err) => {
+                    res.errors.push((path, err));
+                    continue;
+                },
+            };
+            let (srcs, dsts) = files.entry(key).or_default();
+            if is_src { srcs.push(path) } else { dsts.push(path) }
+        }
+    }
+    for (_, (mut srcs, mut dsts)) in files {
+        match (srcs.len(), dsts.len()) {
+            (1, 1) => res.pairs.push((srcs.remove(0), dsts.remove(0))),
+            (_, 0) => res.missing_dst.append(&mut srcs),
+            (0, _) => res.missing_src.append(&mut dsts),
+            _ => res.ambiguous.push((srcs, dsts)),
+        }
+    }
+    res.pairs.sort();
+    res.missing_dst.sort();
+    res.missing_src.sort();
+    res.ambiguous.sort();
+    res
+}
+
+// What `path` in the tree at `root` is matched by, if it can be matched at all.
+fn counterpart_key(root: &Path, path: &Path, by: Counterpart)
+ -> Result<Option<OsString>, ReatError>
+{
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    match by {
+        Counterpart::Path => Ok(Some(rel.as_os_str().to_os_string())),
+        Counterpart::Stem => Ok(Some(rel.with_extension("").into_os_string())),
+        Counterpart::Hash if !fs::metadata(path)?.is_file() => Ok(None),
+        Counterpart::Hash => {
+            let mut file = fs::File::open(path)?;
+            let mut hasher = DefaultHasher::new();
+            let mut buf = [0; 1 << 16];
+            let mut len = 0;
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        hasher.write(&buf[..n]);
+                        len += n;
+                    },
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => { },
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            Ok(Some(format!("{len}:{:016x}", hasher.finish()).into()))
+        },
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{
+    error::ReatError,
+    glob::Glob,
+};
+
+use std::{
+    env, fmt, fs,
+    path::PathBuf,
+};
+
+/// A way an attribute can be kept from changing without force.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq)]
+pub enum Rule {
+    /// An existing value can not be replaced by another, but lists can still be edited.
+    NoOverwrite,
+    /// The attribute can not be removed.
+    NoRemove,
+    /// The value can only grow at the end, so list items can only be added.
+    /// Implies the attribute can not be removed either.
+    AppendOnly,
+    /// The key is outside the `user` namespace. Not configured, but applies with `user_only`.
+    Namespace,
+}
+
+impl Rule {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "nooverwrite" => Some(Rule::NoOverwrite),
+            "noremove" => Some(Rule::NoRemove),
+            "appendonly" => Some(Rule::AppendOnly),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::NoOverwrite => write!(f, "protected from overwriting"),
+            Rule::NoRemove => write!(f, "protected from removal"),
+            Rule::AppendOnly => write!(f, "append-only"),
+            Rule::Namespace => write!(f, "outside the user namespace"),
+        }
+    }
+}
+
+/// The rules that apply to a key.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub struct Rules {
+    pub no_overwrite: bool,
+    pub no_remove: bool,
+    pub append_only: bool,
+}
+
+impl Rules {
+    fn add(&mut self, rule: Rule) {
+        match rule {
+            Rule::NoOverwrite => self.no_overwrite = true,
+            Rule::NoRemove => self.no_remove = true,
+            Rule::AppendOnly => self.append_only = true,
+            Rule::Namespace => { },
+        }
+    }
+}
+
+/// Keys, or patterns of keys, and the rules that protect them.
+///
+/// The configuration holds whitespace separated `pattern=rule,rule` entries, where the pattern
+/// is a glob over keys and the rules are `nooverwrite`, `noremove` and `appendonly`.
+/// A `#` starts a comment that runs to the end of the line.
+/// User keys are matched without `user.`, keys in other namespaces with theirs.
+/// The default, without a configuration, protects `tags` from overwriting and removal.
+/// An empty protection protects nothing.
+#[derive(Clone, Debug, Default)]
+pub struct Protections {
+    pub entries: Vec<(Glob, Rules)>,
+    /// Keys outside the `user` namespace can not be changed at all.
+    pub user_only: bool,
+}
+
+impl Protections {
+    /// The configuration file in the user config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config.join("reat").join("protect"))
+    }
+
+    /// The protections in `$REAT_PROTECT` if it is set, or else in the configuration file.
+    /// Without either, `tags` is protected from overwriting and removal.
+    /// Keys outside the `user` namespace are always protected.
+    pub fn load() -> Result<Self, ReatError> {
+        let config = match env::var("REAT_PROTECT") {
+            Ok(config) => config,
+            Err(_) => match Self::default_path().map(fs::read_to_string) {
+                Some(Ok(config)) => config,
+                Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(err.into());
+                },
+                _ => "tags=nooverwrite,noremove".to_string(),
+            },
+        };
+        Ok(Self { user_only: true, ..Self::parse(&config)? })
+    }
+
+    pub fn parse(config: &str) -> Result<Self, ReatError> {
+        let mut res = Self::default();
+        for (nr, line) in config.lines().enumerate() {
+            let line = line.split_once('#').map(|(line, _)| line).unwrap_or(line);
+            for entry in line.split_whitespace() {
+                let (pattern, names) = entry.rsplit_once('=').ok_or(ReatError::Parse(nr + 1))?;
+                let mut rules = Rules::default();
+                for name in names.split(',') {
+                    rules.add(Rule::parse(name).ok_or(ReatError::Parse(nr + 1))?);
+                }
+                res.entries.push((Glob::new(pattern), rules));
+            }
+        }
+        Ok(res)
+    }
+
+    /// The rules of every pattern that matches `key` together, where user keys are given
+    /// without `user.` like in the patterns.
+    pub fn rules(&self, key: &str) -> Rules {
+        let mut res = Rules::default();
+        for (glob, rules) in &self.entries {
+            if glob.matches(key) {
+                res.no_overwrite |= rules.no_overwrite;
+                res.no_remove |= rules.no_remove;
+                res.append_only |= rules.append_only;
+            }
+        }
+        res
+    }
+
+    // Check the namespace of the fully qualified `key` and give its rules.
+    // Every key that does not start with `user.` is outside the user namespace.
+    fn qualified_rules(&self, key: &str) -> Result<Rules, Rule> {
+        match key.strip_prefix("user.") {
+            Some(key) => Ok(self.rules(key)),
+            None if self.user_only => Err(Rule::Namespace),
+            None => Ok(self.rules(key)),
+        }
+    }
+
+    /// Check that the fully qualified `key` may change from `old` to `new`, where `None` is a
+    /// missing value. List edits are not overwrites. Fails with the rule that would be broken.
+    pub fn check(&self, key: &str, old: Option<&[u8]>, new: Option<&[u8]>, edit: bool)
+     -> Result<(), Rule>
+    {
+        let rules = self.qualified_rules(key)?;
+        let Some(old) = old else { return Ok(()) };
+        match new {
+            None if rules.no_remove => Err(Rule::NoRemove),
+            None if rules.append_only => Err(Rule::AppendOnly),
+            Some(new) if rules.append_only && !new.starts_with(old) => Err(Rule::AppendOnly),
+            Some(new) if rules.no_overwrite && !edit && new != old => Err(Rule::NoOverwrite),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the list in the fully qualified `key` may be edited, where `drops` is whether
+    /// the edit can drop part of the value, such as cutting, replacing or sorting items does.
+    pub fn check_edit(&self, key: &str, drops: bool) -> Result<(), Rule> {
+        let rules = self.qualified_rules(key)?;
+        if drops && rules.append_only { Err(Rule::AppendOnly) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_of_qualified_keys() {
+        let protect = Protections::parse("tags=noremove").unwrap();
+        let protect = Protections { user_only: true, ..protect };
+        assert_eq!(protect.check("user.mood", None, Some(b"x"), false), Ok(()));
+        for key in ["btrfs.compression", "trusted.foo", "foo", "tags", "user"] {
+            assert_eq!(protect.check(key, None, Some(b"x"), false), Err(Rule::Namespace), "{key}");
+            assert_eq!(protect.check_edit(key, false), Err(Rule::Namespace), "{key}");
+        }
+        assert_eq!(protect.check("user.tags", Some(b"x"), None, false), Err(Rule::NoRemove));
+        let protect = Protections::parse("trusted.*=nooverwrite").unwrap();
+        assert_eq!(protect.check("btrfs.compression", None, Some(b"x"), false), Ok(()));
+        let check = protect.check("trusted.foo", Some(b"x"), Some(b"y"), false);
+        assert_eq!(check, Err(Rule::NoOverwrite));
+    }
+}
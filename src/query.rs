@@ -0,0 +1,215 @@
+use crate::{
+    error::ReatError,
+    matcher::{ Matcher, MatchMode },
+};
+
+/// A boolean expression over the attributes of a file.
+///
+/// ```text
+/// expr := and ("or" and)*
+/// and  := not ("and" not)*
+/// not  := "not" not | "(" expr ")" | term
+/// term := attr ":" value | attr
+/// ```
+///
+/// `attr:value` holds when an item of the list in `attr` matches `value`,
+/// a bare `attr` holds when the attribute exists. `&`, `|` and `!` can be used instead of the
+/// words and values can be quoted with `"`.
+#[derive(Clone, Debug)]
+pub enum Query {
+    Or(Box<Query>, Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Contains(String, Matcher),
+    Exists(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Or,
+    And,
+    Not,
+    Open,
+    Close,
+    Term(String, Option<String>),
+}
+
+impl Query {
+    /// Parse a query. Values are matched with `mode`, or the default mode of their attribute.
+    pub fn parse(input: &str, mode: Option<MatchMode>, nocase: bool) -> Result<Self, ReatError> {
+        let tokens = tokenize(input)?;
+        let mut pos = 0;
+        let query = parse_or(&tokens, &mut pos, &|key, value| {
+            Matcher::new(value, mode.unwrap_or(MatchMode::default_for(key)), nocase)
+        })?;
+        if pos < tokens.len() {
+            return Err(ReatError::InvalidQuery(format!("unexpected {}", tokens[pos])));
+        }
+        Ok(query)
+    }
+
+    /// Evaluate the query, `lookup` gives the list items of an attribute if it exists.
+    pub fn eval<F: Fn(&str) -> Option<Vec<String>>>(&self, lookup: &F) -> bool {
+        match self {
+            Query::Or(a, b) => a.eval(lookup) || b.eval(lookup),
+            Query::And(a, b) => a.eval(lookup) && b.eval(lookup),
+            Query::Not(a) => !a.eval(lookup),
+            Query::Contains(key, matcher) => lookup(key)
+                .is_some_and(|list| list.iter().any(|item| matcher.matches(item))),
+            Query::Exists(key) => lookup(key).is_some(),
+        }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Or => write!(f, "or"),
+            Token::And => write!(f, "and"),
+            Token::Not => write!(f, "not"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Term(key, None) => write!(f, "{key}"),
+            Token::Term(key, Some(value)) => write!(f, "{key}:{value}"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ReatError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); },
+            '(' => { chars.next(); tokens.push(Token::Open); },
+            ')' => { chars.next(); tokens.push(Token::Close); },
+            '|' => { chars.next(); tokens.push(Token::Or); },
+            '&' => { chars.next(); tokens.push(Token::And); },
+            '!' => { chars.next(); tokens.push(Token::Not); },
+            _ => {
+                let key = word(&mut chars, true)?;
+                if key.is_empty() {
+                    return Err(ReatError::InvalidQuery(format!("unexpected {c}")));
+                }
+                let value = if chars.peek() == Some(&':') {
+                    chars.next();
+                    Some(word(&mut chars, false)?)
+                } else {
+                    None
+                };
+                tokens.push(match (key.as_str(), value) {
+                    ("or", None) => Token::Or,
+                    ("and", None) => Token::And,
+                    ("not", None) => Token::Not,
+                    (_, value) => Token::Term(key, value),
+                });
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+// A bare or quoted word, ending at whitespace, a parenthesis or an operator.
+// Only keys end at a colon, so a value is split off at the first one.
+fn word(chars: &mut std::iter::Peekable<std::str::Chars>, key: bool)
+ -> Result<String, ReatError>
+{
+    let mut res = String::new();
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(res),
+                Some('\\') => res.extend(chars.next()),
+                Some(c) => res.push(c),
+                None => return Err(ReatError::InvalidQuery("unclosed quote".to_string())),
+            }
+        }
+    }
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || "()|&!\"".contains(c) || (key && c == ':') {
+            break;
+        }
+        res.push(c);
+        chars.next();
+    }
+    Ok(res)
+}
+
+type Compile<'a> = &'a dyn Fn(&str, &str) -> Result<Matcher, ReatError>;
+
+fn parse_or(tokens: &[Token], pos: &mut usize, compile: Compile) -> Result<Query, ReatError> {
+    let mut res = parse_and(tokens, pos, compile)?;
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        res = Query::Or(Box::new(res), Box::new(parse_and(tokens, pos, compile)?));
+    }
+    Ok(res)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize, compile: Compile) -> Result<Query, ReatError> {
+    let mut res = parse_not(tokens, pos, compile)?;
+    while tokens.get(*pos) == Some(&Token::And) {
+        *pos += 1;
+        res = Query::And(Box::new(res), Box::new(parse_not(tokens, pos, compile)?));
+    }
+    Ok(res)
+}
+
+fn parse_not(tokens: &[Token], pos: &mut usize, compile: Compile) -> Result<Query, ReatError> {
+    let token = tokens.get(*pos)
+        .ok_or_else(|| ReatError::InvalidQuery("unexpected end".to_string()))?;
+    *pos += 1;
+    match token {
+        Token::Not => Ok(Query::Not(Box::new(parse_not(tokens, pos, compile)?))),
+        Token::Open => {
+            let res = parse_or(tokens, pos, compile)?;
+            if tokens.get(*pos) != Some(&Token::Close) {
+                return Err(ReatError::InvalidQuery("missing )".to_string()));
+            }
+            *pos += 1;
+            Ok(res)
+        },
+        Token::Term(key, Some(value)) => Ok(Query::Contains(key.clone(), compile(key, value)?)),
+        Token::Term(key, None) => Ok(Query::Exists(key.clone())),
+        token => Err(ReatError::InvalidQuery(format!("unexpected {token}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(query: &str, attrs: &[(&str, &[&str])]) -> bool {
+        let query = Query::parse(query, None, false).unwrap();
+        query.eval(&|key| attrs.iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, items)| items.iter().map(|item| item.to_string()).collect()))
+    }
+
+    #[test]
+    fn operators() {
+        let attrs: &[(&str, &[&str])] = &[("tags", &["cat", "dog"]), ("note", &["a long note"])];
+        assert!(eval("tags:cat and note:long", attrs));
+        assert!(!eval("tags:ca", attrs));
+        assert!(eval("tags:bird or (note & !tags:bird)", attrs));
+        assert!(eval("not not tags", attrs) && !eval("!note | rating", attrs));
+        assert!(eval("note:\"long note\" & tags:\"dog\"", attrs));
+        assert!(eval("a or b and c", &[("a", &[])]) && !eval("(a or b) and c", &[("a", &[])]));
+    }
+
+    #[test]
+    fn values_with_colons() {
+        let attrs: &[(&str, &[&str])] = &[("date", &["2024-01-01T10:30"]), ("url", &["a:b:c"])];
+        assert!(eval("date:2024-01-01T10:30", attrs));
+        assert!(eval("date:10:30 and url::b:", attrs));
+        assert!(!eval("date:11:30", attrs));
+    }
+
+    #[test]
+    fn invalid() {
+        for query in ["", "(tags", "tags)", "and", "tags:\"open", "tags or", ":x", "not"] {
+            assert!(Query::parse(query, None, false).is_err(), "{query}");
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+
+/// How attribute values are compared when sorting.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Order {
+    /// By bytes, so `10` comes before `9`.
+    #[default]
+    Lexicographic,
+    /// Numbers by their value, and numbers inside text as well, so `file9` comes before `file10`
+    /// and `1.9` before `1.10`. A value can start with a minus sign.
+    Natural,
+    /// Dates and times written year first, such as `2024-03-05` or `2024-03-05T10:30:00`.
+    Date,
+    /// Versions such as `1.10.0`, `v2.0` or `1.0.0-rc1`, where a pre-release comes before
+    /// its release.
+    Version,
+}
+
+/// Where sorting puts files without the attribute.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Missing {
+    First,
+    #[default]
+    Last,
+    /// Leave them out.
+    Drop,
+}
+
+/// How `sort_by_value` orders items.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub struct SortOptions {
+    pub order: Order,
+    /// Put the largest value first.
+    pub descending: bool,
+    pub missing: Missing,
+}
+
+impl Order {
+    /// Whether `value` can be compared. Only dates can fail to parse.
+    pub fn valid(&self, value: &str) -> bool {
+        *self != Order::Date || date(value).is_some()
+    }
+
+    /// Compare two values. Dates that do not parse come first.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let (a, b) = (a.trim(), b.trim());
+        match self {
+            Order::Lexicographic => a.cmp(b),
+            Order::Natural => natural(a, b),
+            Order::Date => date(a).cmp(&date(b)),
+            Order::Version => version(a, b),
+        }
+    }
+}
+
+/// Sort `items` by their values as compared by the order in `options`, keeping the order of
+/// equal values. Items without a value, or with one the order can not compare, go where
+/// `missing` says.
+pub fn sort_by_value<T>(items: Vec<(T, Option<String>)>, options: SortOptions) -> Vec<T> {
+    let SortOptions { order, descending, missing } = options;
+    let (mut present, absent): (Vec<_>, Vec<_>) = items.into_iter()
+        .partition(|(_, value)| value.as_ref().is_some_and(|value| order.valid(value)));
+    present.sort_by(|(_, a), (_, b)| {
+        let ord = order.compare(a.as_deref().unwrap_or(""), b.as_deref().unwrap_or(""));
+        if descending { ord.reverse() } else { ord }
+    });
+    let present = present.into_iter().map(|(item, _)| item);
+    let absent = absent.into_iter().map(|(item, _)| item);
+    match missing {
+        Missing::First => absent.chain(present).collect(),
+        Missing::Last => present.chain(absent).collect(),
+        Missing::Drop => present.collect(),
+    }
+}
+
+// A number or any other character of a value compared naturally.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token<'a> {
+    /// Digits with an optional fraction after a dot, and a sign if the value starts with one.
+    Number { negative: bool, int: &'a str, frac: &'a str },
+    Char(char),
+}
+
+impl Ord for Token<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (
+                Token::Number { negative: neg_a, int: int_a, frac: frac_a },
+                Token::Number { negative: neg_b, int: int_b, frac: frac_b },
+            ) => {
+                let (int_a, int_b) = (int_a.trim_start_matches('0'), int_b.trim_start_matches('0'));
+                let (frac_a, frac_b) = (frac_a.trim_end_matches('0'), frac_b.trim_end_matches('0'));
+                let magnitude = int_a.len().cmp(&int_b.len())
+                    .then_with(|| int_a.cmp(int_b))
+                    .then_with(|| frac_a.cmp(frac_b));
+                match (neg_a, neg_b) {
+                    (false, false) => magnitude,
+                    (true, true) => magnitude.reverse(),
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                }
+            },
+            // Every number is on the same side of a character that is not a digit.
+            (Token::Number { .. }, Token::Char(c)) => '0'.cmp(c),
+            (Token::Char(c), Token::Number { .. }) => c.cmp(&'0'),
+            (Token::Char(a), Token::Char(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Token<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn tokens(value: &str) -> Vec<Token<'_>> {
+    let mut res = Vec::new();
+    let mut rest = value;
+    let signed = value.strip_prefix('-')
+        .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+    if let Some(signed) = signed {
+        rest = signed;
+    }
+    while let Some(c) = rest.chars().next() {
+        if !c.is_ascii_digit() {
+            res.push(Token::Char(c));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (int, after) = split_run(rest, |c| c.is_ascii_digit());
+        let (frac, after) = match after.strip_prefix('.') {
+            Some(frac) if frac.starts_with(|c: char| c.is_ascii_digit()) =>
+                split_run(frac, |c| c.is_ascii_digit()),
+            _ => ("", after),
+        };
+        res.push(Token::Number { negative: res.is_empty() && signed.is_some(), int, frac });
+        rest = after;
+    }
+    res
+}
+
+// Compare numbers by their value and everything else by character.
+fn natural(a: &str, b: &str) -> Ordering {
+    tokens(a).cmp(&tokens(b))
+}
+
+fn split_run<F: Fn(char) -> bool>(s: &str, f: F) -> (&str, &str) {
+    s.split_at(s.find(|c| !f(c)).unwrap_or(s.len()))
+}
+
+// The numbers of a date written year first, from the year down.
+fn date(value: &str) -> Option<Vec<u64>> {
+    let parts = value.trim().split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let year = value.trim().split(|c: char| !c.is_ascii_digit()).next()?;
+    (year.len() == 4).then_some(parts)
+}
+
+// Compare the release numbers, then put a pre-release before its release.
+// Build metadata after a `+` is ignored.
+fn version(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| {
+        let v = v.strip_prefix(['v', 'V']).unwrap_or(v);
+        let v = v.split_once('+').map(|(v, _)| v).unwrap_or(v);
+        match v.split_once('-') {
+            Some((release, pre)) => (release.to_string(), Some(pre.to_string())),
+            None => (v.to_string(), None),
+        }
+    };
+    let ((release_a, pre_a), (release_b, pre_b)) = (split(a), split(b));
+    let mut parts_a = release_a.split(['.', '_']);
+    let mut parts_b = release_b.split(['.', '_']);
+    loop {
+        let ord = match (parts_a.next(), parts_b.next()) {
+            (None, None) => break,
+            (x, y) => natural(x.unwrap_or("0"), y.unwrap_or("0")),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    match (pre_a, pre_b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(x), Some(y)) => natural(&x, &y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(values: &[Option<&str>], order: Order, descending: bool, missing: Missing)
+     -> Vec<usize>
+    {
+        let items = values.iter().enumerate()
+            .map(|(i, value)| (i, value.map(String::from)))
+            .collect();
+        sort_by_value(items, SortOptions { order, descending, missing })
+    }
+
+    #[test]
+    fn natural_mixes_numbers_and_text() {
+        let values = [Some("1.10"), Some("1.9x"), Some("1.9"), Some("10"), Some("9"), Some("-2"),
+            Some("-10"), Some("a"), Some("file10"), Some("file9")];
+        assert_eq!(
+            sorted(&values, Order::Natural, false, Missing::Last),
+            vec![6, 5, 0, 2, 1, 4, 3, 7, 9, 8]
+        );
+    }
+
+    #[test]
+    fn natural_is_a_total_order() {
+        let values = ["", "3", "3.7", "3.7x", "3.70", "03", "1.10", "1.9", "1.9x", "-1", "-1.5",
+            "-x", "x", "x3", "x10", "3.", "3.x", ".5", "1.2.3", "a b"];
+        for a in values {
+            assert_eq!(natural(a, a), Ordering::Equal);
+            for b in values {
+                assert_eq!(natural(a, b), natural(b, a).reverse(), "{a} {b}");
+                for c in values {
+                    if natural(a, b) != Ordering::Greater && natural(b, c) != Ordering::Greater {
+                        assert_ne!(natural(a, c), Ordering::Greater, "{a} {b} {c}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn many_mixed_values_sort() {
+        let values = (0..300)
+            .map(|i| match i % 3 {
+                0 => format!("{}", i % 7),
+                1 => format!("{}.{}", i % 7, i % 10),
+                _ => format!("{}.{}x", i % 7, i % 10),
+            })
+            .collect::<Vec<_>>();
+        let values = values.iter().map(|value| Some(value.as_str())).collect::<Vec<_>>();
+        let res = sorted(&values, Order::Natural, false, Missing::Last);
+        for pair in res.windows(2) {
+            let (a, b) = (values[pair[0]].unwrap(), values[pair[1]].unwrap());
+            assert_ne!(natural(a, b), Ordering::Greater);
+        }
+    }
+
+    #[test]
+    fn missing_values() {
+        let values = [Some("b"), None, Some("a")];
+        assert_eq!(sorted(&values, Order::Lexicographic, false, Missing::First), vec![1, 2, 0]);
+        assert_eq!(sorted(&values, Order::Lexicographic, false, Missing::Last), vec![2, 0, 1]);
+        assert_eq!(sorted(&values, Order::Lexicographic, true, Missing::Drop), vec![0, 2]);
+    }
+
+    #[test]
+    fn dates_and_versions() {
+        let dates = [Some("2024-03-05"), Some("05-03-2024"), Some("2023-12-31T10:00"), Some("2024")];
+        assert_eq!(sorted(&dates, Order::Date, false, Missing::Drop), vec![2, 3, 0]);
+        let versions = [Some("v1.10.0"), Some("1.9"), Some("1.10.0-rc1"), Some("1.10.0-rc10")];
+        assert_eq!(sorted(&versions, Order::Version, false, Missing::Last), vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn equal_values_keep_their_order() {
+        let values = [Some("1"), Some("01"), Some("1.0")];
+        assert_eq!(sorted(&values, Order::Natural, false, Missing::Last), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    error::ReatError,
+    glob::Glob,
+};
+
+use std::{
+    fs,
+    path::{ Path, PathBuf },
+    collections::HashSet,
+    os::unix::fs::MetadataExt,
+};
+
+/// Which symlinks to directories are walked into.
+#[derive(Clone, Copy, Hash, Debug, Default, Eq, PartialEq)]
+pub enum Links {
+    /// Only roots that are symlinks, like `find -H`.
+    #[default]
+    Roots,
+    /// Every symlink, like `find -L`.
+    All,
+    /// None of them, not even roots, like `find -P`.
+    Never,
+}
+
+/// Options for walking directory trees.
+#[derive(Clone, Debug, Default)]
+pub struct Walker {
+    /// Deepest level to walk to, the root is at level 0.
+    pub max_depth: Option<usize>,
+    /// Walk into and return files and directories starting with a dot.
+    pub hidden: bool,
+    /// Which symlinked directories to walk into.
+    pub links: Links,
+    /// Only return files whose name matches one of these, if there are any.
+    pub include: Vec<Glob>,
+    /// Skip files and directories whose name matches one of these.
+    pub exclude: Vec<Glob>,
+}
+
+impl Walker {
+    /// Walk `root` and everything below it, depth first and in sorted order.
+    /// `root` itself comes first if `include` and `exclude` let it through, it is walked into
+    /// either way, also when it is hidden. Directories that could not be read are returned
+    /// as errors.
+    pub fn walk<P: AsRef<Path>>(&self, root: P) -> Vec<Result<PathBuf, (PathBuf, ReatError)>> {
+        let root = root.as_ref();
+        let mut res = Vec::new();
+        let name = root.file_name().unwrap_or_default().to_string_lossy();
+        if !self.excluded(&name) && self.included(&name) {
+            res.push(Ok(root.to_path_buf()));
+        }
+        let mut visited = HashSet::new();
+        let meta = match self.links {
+            Links::Never => fs::symlink_metadata(root),
+            _ => fs::metadata(root),
+        };
+        if meta.is_ok_and(|meta| meta.is_dir()) {
+            self.walk_dir(root, 1, &mut visited, &mut res);
+        }
+        res
+    }
+
+    fn walk_dir(
+        &self, dir: &Path, depth: usize, visited: &mut HashSet<(u64, u64)>,
+        res: &mut Vec<Result<PathBuf, (PathBuf, ReatError)>>
+    ) {
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+        // Following symlinks can lead into cycles.
+        if let Ok(meta) = fs::metadata(dir) && !visited.insert((meta.dev(), meta.ino())) {
+            return;
+        }
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                res.push(Err((dir.to_path_buf(), err.into())));
+                return;
+            },
+        };
+        let mut entries = entries.filter_map(Result::ok).map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        for path in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !self.hidden && name.starts_with('.') {
+                continue;
+            }
+            if self.excluded(&name) {
+                continue;
+            }
+            if self.included(&name) {
+                res.push(Ok(path.clone()));
+            }
+            let meta = match self.links {
+                Links::All => fs::metadata(&path),
+                _ => fs::symlink_metadata(&path),
+            };
+            if meta.is_ok_and(|meta| meta.is_dir()) {
+                self.walk_dir(&path, depth + 1, visited, res);
+            }
+        }
+    }
+
+    fn included(&self, name: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| glob.matches(name))
+    }
+
+    fn excluded(&self, name: &str) -> bool {
+        self.exclude.iter().any(|glob| glob.matches(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_apply_to_the_root() {
+        let root = std::env::temp_dir().join(format!("reat-walk-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["a.jpg", "b.txt", "sub/c.jpg", ".d.jpg"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let walk = |walker: &Walker| walker.walk(&root).into_iter()
+            .map(|path| path.unwrap().strip_prefix(&root).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        let walker = Walker { include: vec![Glob::new("*.jpg")], ..Default::default() };
+        let included = walk(&walker);
+        let walker = Walker { exclude: vec![Glob::new("reat-*")], ..Default::default() };
+        let excluded = walk(&walker);
+        let all = walk(&Walker::default());
+        fs::remove_dir_all(&root).unwrap();
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(included, paths(&["a.jpg", "sub/c.jpg"]));
+        assert_eq!(excluded, paths(&["a.jpg", "b.txt", "sub", "sub/c.jpg"]));
+        assert_eq!(all, paths(&["", "a.jpg", "b.txt", "sub", "sub/c.jpg"]));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{ Command, Output, Stdio },
+};
+
+// A fresh directory holding empty files, with its own journal, index and no protections.
+struct Dir {
+    path: PathBuf,
+}
+
+impl Dir {
+    fn new(name: &str, files: &[&str]) -> Self {
+        let path = env::temp_dir().join(format!("reat-cli-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        for file in files {
+            fs::write(path.join(file), "").unwrap();
+        }
+        Self { path }
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_reat"));
+        command.args(args)
+            .current_dir(&self.path)
+            .env("REAT_JOURNAL", self.path.join(".journal"))
+            .env("REAT_INDEX", self.path.join(".index"))
+            .env("REAT_PROTECT", "")
+            .env("NO_COLOR", "1");
+        command
+    }
+
+    fn reat(&self, args: &[&str]) -> Output {
+        self.command(args).stdin(Stdio::null()).output().unwrap()
+    }
+
+    fn reat_stdin(&self, args: &[&str], input: &[u8]) -> Output {
+        let mut child = self.command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    // The user attribute `key` of `file`, if it has one.
+    fn get(&self, file: &str, key: &str) -> Option<String> {
+        xattr::get(self.path.join(file), format!("user.{key}")).unwrap()
+            .map(|value| String::from_utf8(value).unwrap())
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn code(output: &Output) -> i32 {
+    output.status.code().unwrap()
+}
+
+#[test]
+fn option_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("sorted", &["a", "b"]);
+    assert_eq!(code(&dir.reat(&["set", "status", "sorted", "a", "b"])), 0);
+    assert_eq!(dir.get("a", "status").as_deref(), Some("sorted"));
+    assert_eq!(dir.get("b", "status").as_deref(), Some("sorted"));
+    assert_eq!(code(&dir.reat(&["sorted", "add", "tags", "z,x", "a"])), 0);
+    assert_eq!(code(&dir.reat(&["sorted", "add", "tags", "y", "a"])), 0);
+    assert_eq!(dir.get("a", "tags").as_deref(), Some("x,y,z"));
+    assert_eq!(code(&dir.reat(&["add", "tags", "sorted", "b"])), 0);
+    assert_eq!(dir.get("b", "tags").as_deref(), Some("sorted"));
+}
+
+#[test]
+fn multi_pairs_in_both_orders() {
+    let dir = Dir::new("multi", &["a", "b"]);
+    let output = dir.reat(&["set", "m", "author", "al", "project", "p1", "-", "a", "b"]);
+    assert_eq!(code(&output), 0);
+    for file in ["a", "b"] {
+        assert_eq!(dir.get(file, "author").as_deref(), Some("al"));
+        assert_eq!(dir.get(file, "project").as_deref(), Some("p1"));
+        assert_eq!(dir.get(file, "m"), None);
+    }
+    // Words that are options elsewhere are values once the pairs start.
+    assert_eq!(code(&dir.reat(&["m", "rating", "0", "mood", "n", "set", "a"])), 0);
+    assert_eq!(dir.get("a", "rating").as_deref(), Some("0"));
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("n"));
+    assert_eq!(code(&dir.reat(&["add", "multi", "tags", "h", "tags", "v", "-", "b"])), 0);
+    assert_eq!(dir.get("b", "tags").as_deref(), Some("h,v"));
+    assert_eq!(code(&dir.reat(&["set", "m", "rating", "-", "a"])), 2);
+}
+
+#[test]
+fn dry_after_the_mode_is_a_value() {
+    let dir = Dir::new("dry", &["a", "b"]);
+    assert_eq!(code(&dir.reat(&["set", "mood", "dry", "a", "b"])), 0);
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("dry"));
+    assert_eq!(dir.get("b", "mood").as_deref(), Some("dry"));
+    let output = dir.reat(&["n", "set", "mood", "wet", "a"]);
+    assert_eq!(code(&output), 0);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("wet"));
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("dry"));
+}
+
+#[test]
+fn json_words_and_encodings() {
+    let dir = Dir::new("json", &["a"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "json", "a"])), 0);
+    assert_eq!(dir.get("a", "tags").as_deref(), Some("json"));
+    xattr::set(dir.path.join("a"), "user.bin", b"\xff\x00").unwrap();
+    let dump = |args: &[&str]| String::from_utf8(dir.reat(args).stdout).unwrap();
+    assert!(dump(&["json", "dump", "a"]).contains("\"0xff00\""));
+    assert!(dump(&["json", "base64", "dump", "a"]).contains("\"0s/wA=\""));
+    // Modes without results do not print an empty array.
+    let output = dir.reat(&["json", "set", "mood", "x", "a"]);
+    assert_eq!(code(&output), 2);
+    assert!(output.stdout.is_empty());
+    assert_eq!(dir.get("a", "mood"), None);
+    let output = dir.reat(&["json", "dry", "set", "mood", "x", "a"]);
+    assert_eq!(code(&output), 0);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"new\""));
+}
+
+#[test]
+fn walk_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("walk", &["a", "hidden", "follow"]);
+    for file in ["hidden", "follow"] {
+        assert_eq!(code(&dir.reat(&["add", "tags", "x", file])), 0);
+    }
+    let output = dir.reat(&["cn", "tags", "x", "hidden", "a", "follow"]);
+    assert_eq!(code(&output), 0);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hidden\nfollow\n");
+    assert_eq!(code(&dir.reat(&["add", "tags", "hidden", "a"])), 0);
+    let output = dir.reat(&["cn", "tags", "hidden", "a"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a\n");
+    assert_eq!(code(&dir.reat(&["set", "mood", "rec", "a"])), 0);
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("rec"));
+}
+
+#[test]
+fn sort_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("desc", &["desc", "b"]);
+    assert_eq!(code(&dir.reat(&["set", "rating", "2", "desc"])), 0);
+    assert_eq!(code(&dir.reat(&["set", "rating", "1", "b"])), 0);
+    let sorted = |args: &[&str]| String::from_utf8(dir.reat(args).stdout).unwrap();
+    assert_eq!(sorted(&["sort", "rating", "desc", "b"]), "b\ndesc\n");
+    assert_eq!(sorted(&["desc", "sort", "rating", "desc", "b"]), "desc\nb\n");
+    assert_eq!(code(&dir.reat(&["set", "order", "desc", "b"])), 0);
+    assert_eq!(dir.get("b", "order").as_deref(), Some("desc"));
+}
+
+#[test]
+fn null_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("null", &["a", "b c"]);
+    assert_eq!(code(&dir.reat(&["set", "rating", "0", "a"])), 0);
+    assert_eq!(dir.get("a", "rating").as_deref(), Some("0"));
+    assert_eq!(code(&dir.reat(&["set", "mode", "null", "a"])), 0);
+    assert_eq!(dir.get("a", "mode").as_deref(), Some("null"));
+    assert_eq!(code(&dir.reat_stdin(&["0", "i", "set", "rating", "1"], b"a\0b c\0")), 0);
+    assert_eq!(dir.get("a", "rating").as_deref(), Some("1"));
+    assert_eq!(dir.get("b c", "rating").as_deref(), Some("1"));
+    let output = dir.reat(&["null", "cn", "rating", "1", "a", "b c"]);
+    assert_eq!(output.stdout, b"a\0b c\0");
+}
+
+#[test]
+fn nofollow_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("nofollow", &["a"]);
+    std::os::unix::fs::symlink("a", dir.path.join("h")).unwrap();
+    assert_eq!(code(&dir.reat(&["set", "links", "nofollow", "h"])), 0);
+    assert_eq!(dir.get("a", "links").as_deref(), Some("nofollow"));
+    assert_eq!(code(&dir.reat(&["set", "mood", "x", "h"])), 0);
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("x"));
+    // Only the link itself is changed, which user attributes are not allowed on.
+    assert_ne!(code(&dir.reat(&["h", "set", "mood", "y", "h"])), 0);
+    assert_eq!(dir.get("a", "mood").as_deref(), Some("x"));
+}
+
+#[test]
+fn encoding_words_after_the_mode_are_arguments() {
+    let dir = Dir::new("hex", &["a"]);
+    assert_eq!(code(&dir.reat(&["set", "enc", "hex", "a"])), 0);
+    assert_eq!(dir.get("a", "enc").as_deref(), Some("hex"));
+    assert_eq!(code(&dir.reat(&["add", "enc", "base64", "a"])), 0);
+    assert_eq!(dir.get("a", "enc").as_deref(), Some("hex,base64"));
+    xattr::set(dir.path.join("a"), "user.bin", b"\xff").unwrap();
+    let output = dir.reat(&["b64", "get", "bin", "a"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("0s/w=="));
+}
+
+#[test]
+fn query_words() {
+    let dir = Dir::new("query", &["one", "two"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "x", "one", "two"])), 0);
+    assert_eq!(code(&dir.reat(&["add", "tags", "draft", "two"])), 0);
+    let query = |args: &[&str]| {
+        let output = dir.reat(args);
+        (code(&output), String::from_utf8(output.stdout).unwrap())
+    };
+    let words = ["tags:x", "and", "not", "tags:draft"];
+    assert_eq!(query(&[&["q"], &words[..], &["-", "one", "two"]].concat()), (0, "one\n".into()));
+    assert_eq!(query(&[&["one", "two", "q"], &words[..]].concat()), (0, "one\n".into()));
+    assert_eq!(query(&["q", "tags:x and not tags:draft", "one", "two"]), (0, "one\n".into()));
+    assert_eq!(query(&[&["q"], &words[..], &["one", "two"]].concat()), (2, String::new()));
+    let output = dir.reat_stdin(&[&["i", "q"], &words[..]].concat(), b"one\ntwo\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\n");
+}
+
+#[test]
+fn cached_reads_changed_files_again() {
+    let dir = Dir::new("cached", &["one", "two"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "new", "one", "two"])), 0);
+    assert_eq!(code(&dir.reat(&["ix", "one", "two"])), 0);
+    assert_eq!(code(&dir.reat(&["f", "set", "tags", "other", "one"])), 0);
+    let output = dir.reat(&["cached", "cn", "tags", "new", "one", "two"]);
+    assert_eq!(code(&output), 0);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "two\n");
+    let output = dir.reat(&["cached", "cn", "tags", "other"]);
+    let one = dir.path.join("one");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", one.display()));
+}
+
+#[test]
+fn unreadable_files_fail() {
+    let dir = Dir::new("failures", &["one"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "x", "one"])), 0);
+    let modes = [&["cn", "tags", "x"][..], &["cnn", "tags", "x"], &["q", "tags:x"], &["sort", "tags"]];
+    for args in modes {
+        let output = dir.reat(&[args, &["nonexist"]].concat());
+        assert_eq!(code(&output), 4, "{args:?}");
+        assert!(!output.stderr.is_empty());
+        let output = dir.reat(&[args, &["one", "nonexist"]].concat());
+        assert_eq!(code(&output), 3, "{args:?}");
+    }
+    assert_eq!(code(&dir.reat(&["rank", "tags", "-", "nonexist"])), 4);
+    assert_eq!(code(&dir.reat(&["cn", "tags", "y", "one"])), 1);
+    assert_eq!(code(&dir.reat(&["cn", "mood", "y", "one"])), 1);
+}
+
+#[test]
+fn cached_reports_unreadable_files() {
+    let dir = Dir::new("cached-failures", &["one", "two"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "x", "one", "two"])), 0);
+    assert_eq!(code(&dir.reat(&["ix", "one", "two"])), 0);
+    fs::remove_file(dir.path.join("two")).unwrap();
+    let output = dir.reat(&["cached", "cn", "tags", "x", "two"]);
+    assert_eq!(code(&output), 4);
+    assert!(output.stdout.is_empty());
+    assert_eq!(code(&dir.reat(&["cached", "cn", "tags", "x"])), 3);
+    assert_eq!(code(&dir.reat(&["cached", "ra", "tags"])), 3);
+    assert_eq!(code(&dir.reat(&["cached", "q", "tags:x", "nonexist"])), 4);
+}
+
+#[test]
+fn restore_keeps_other_namespaces_protected() {
+    let dir = Dir::new("namespaces", &["one"]);
+    let dump = "reat dump 2\nf one\na btrfs.compression zstd\na user.mood x\n";
+    let output = dir.reat_stdin(&["rs"], dump.as_bytes());
+    assert_eq!(code(&output), 3);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("outside the user namespace"));
+    assert_eq!(dir.get("one", "mood").as_deref(), Some("x"));
+}
+
+#[test]
+fn replace_list_items() {
+    let dir = Dir::new("replace", &["one", "two"]);
+    assert_eq!(code(&dir.reat(&["add", "tags", "x", "one", "two"])), 0);
+    assert_eq!(code(&dir.reat(&["add", "tags", "y", "one"])), 0);
+    assert_eq!(code(&dir.reat(&["rp", "tags", "x", "z", "one", "two"])), 0);
+    assert_eq!(dir.get("one", "tags").as_deref(), Some("z,y"));
+    assert_eq!(dir.get("two", "tags").as_deref(), Some("z"));
+}